@@ -1,51 +1,62 @@
 use crate::events::Point;
-use crate::layers::LayerState;
 use crate::layers::Layers;
+use crate::renderer::render_damage;
+use crate::state::State;
+use crate::surface::Surface;
+use js_sys::Array;
+use js_sys::Function;
+use std::cell::Cell;
 use std::cell::RefCell;
+use std::f64::consts::PI;
 use std::rc::Rc;
+use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use wasm_bindgen::JsValue;
+use web_sys::console;
 use web_sys::window;
 use web_sys::CanvasRenderingContext2d;
 use web_sys::HtmlCanvasElement;
 use web_sys::MouseEvent;
 
+/// Sizes are in CSS pixels. The backing store has `pixel_ratio` device pixels for each of
+/// them, so drawings stay sharp on high density displays.
 pub struct Canvas {
     canvas: HtmlCanvasElement,
     context: CanvasRenderingContext2d,
+    width: f64,
+    height: f64,
+    pixel_ratio: f64,
 }
 
 impl Canvas {
     pub fn new(canvas_id: &str) -> Result<Self, JsValue> {
         let canvas = init_canvas(canvas_id)?;
         let context = init_context(&canvas)?;
-        Ok(Canvas { canvas, context })
+        let width = canvas.width().into();
+        let height = canvas.height().into();
+        let mut canvas = Canvas { canvas, context, width, height, pixel_ratio: 1.0 };
+        canvas.set_size(width, height)?;
+        Ok(canvas)
     }
 
-    pub fn render(&self, layers: &Layers) {
-        let canvas = self.canvas();
-        let context = self.context();
-
-        context.begin_path();
-        context.clear_rect(0.0, 0.0, canvas.width().into(), canvas.height().into());
-
-        for layer in layers.layers() {
-            layer.object.draw(context);
-        }
-        if let Some(outlined_layer) = layers.outlined_layer() {
-            layers.layers()[*outlined_layer]
-                .object
-                .draw_outline(context);
-        }
-        match layers.active_layer() {
-            Some(LayerState::Creating { layer, .. })
-            | Some(LayerState::Idle { layer, .. })
-            | Some(LayerState::Resize { layer, .. })
-            | Some(LayerState::Relocate { layer, .. }) => {
-                layers.layers()[*layer].object.draw_active(context);
-            }
-            _ => {}
-        }
+    /// Displays the canvas at `width` by `height` CSS pixels, with a backing store matching
+    /// the display's `devicePixelRatio`.
+    pub fn set_size(&mut self, width: f64, height: f64) -> Result<(), JsValue> {
+        let pixel_ratio = window().ok_or("Window not found")?.device_pixel_ratio();
+        self.width = width;
+        self.height = height;
+        self.pixel_ratio = pixel_ratio;
+        self.canvas.set_width((width * pixel_ratio).round() as u32);
+        self.canvas
+            .set_height((height * pixel_ratio).round() as u32);
+        let style = self.canvas.style();
+        style.set_property("width", &format!("{}px", width))?;
+        style.set_property("height", &format!("{}px", height))?;
+        // Resizing reset the context. Drawing balances every `save` with a `restore`, so this
+        // stays the base transform from here on.
+        self.context
+            .set_transform(pixel_ratio, 0.0, 0.0, pixel_ratio, 0.0, 0.0)?;
+        Ok(())
     }
 
     pub fn canvas(&self) -> &HtmlCanvasElement {
@@ -65,12 +76,224 @@ impl Canvas {
     }
 }
 
+impl Surface for Canvas {
+    fn width(&self) -> f64 {
+        self.width
+    }
+
+    fn height(&self) -> f64 {
+        self.height
+    }
+
+    fn pixel_ratio(&self) -> f64 {
+        self.pixel_ratio
+    }
+
+    fn clear(&self) {
+        self.clear_rect(0.0, 0.0, self.width(), self.height());
+    }
+
+    fn clear_rect(&self, x: f64, y: f64, width: f64, height: f64) {
+        self.context.begin_path();
+        self.context.clear_rect(x, y, width, height);
+    }
+
+    fn clip(&self, x: f64, y: f64, width: f64, height: f64) {
+        self.context.begin_path();
+        self.context.rect(x, y, width, height);
+        self.context.clip();
+    }
+
+    fn begin_path(&self) {
+        self.context.begin_path();
+    }
+
+    fn rect(&self, x: f64, y: f64, width: f64, height: f64) {
+        self.context.rect(x, y, width, height);
+    }
+
+    fn ellipse(&self, x: f64, y: f64, radius_x: f64, radius_y: f64) {
+        self.context
+            .ellipse(x, y, radius_x, radius_y, 0.0, 0.0, 2.0 * PI)
+            .unwrap();
+    }
+
+    fn move_to(&self, x: f64, y: f64) {
+        self.context.move_to(x, y);
+    }
+
+    fn line_to(&self, x: f64, y: f64) {
+        self.context.line_to(x, y);
+    }
+
+    fn set_alpha(&self, alpha: f64) {
+        self.context.set_global_alpha(alpha);
+    }
+
+    fn set_line_dash(&self, dash: &[f64]) {
+        let dash: Array = dash
+            .iter()
+            .map(|&length| JsValue::from_f64(length))
+            .collect();
+        self.context.set_line_dash(&dash).unwrap();
+    }
+
+    fn fill(&self, color: &str) {
+        self.context.set_fill_style_str(color);
+        self.context.fill();
+    }
+
+    fn stroke(&self, color: &str, line_width: f64) {
+        self.context.set_stroke_style_str(color);
+        self.context.set_line_width(line_width);
+        self.context.stroke();
+    }
+
+    fn fill_text(&self, text: &str, x: f64, y: f64, font_size: f64, max_width: f64, color: &str) {
+        self.context
+            .set_font(&format!("{}px sans-serif", font_size));
+        self.context.set_text_baseline("top");
+        self.context.set_fill_style_str(color);
+        self.context
+            .fill_text_with_max_width(text, x, y, max_width)
+            .unwrap();
+    }
+
+    fn set_cursor(&self, cursor: &str) {
+        self.canvas.style().set_property("cursor", cursor).unwrap();
+    }
+
+    fn save(&self) {
+        self.context.save();
+    }
+
+    fn restore(&self) {
+        self.context.restore();
+    }
+
+    fn transform(&self, a: f64, b: f64, c: f64, d: f64, e: f64, f: f64) {
+        self.context.transform(a, b, c, d, e, f).unwrap();
+    }
+}
+
+/// Whether the canvas follows the size of the element around it. That element must hold the
+/// canvas alone and get its size from the page, as otherwise a larger canvas makes it larger,
+/// which makes the canvas larger again.
+pub struct AutoSize {
+    enabled: bool,
+}
+
+impl AutoSize {
+    pub fn new() -> Self {
+        AutoSize { enabled: true }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// The size a `canvas` sized canvas should take now that the content box of its container
+    /// is `container`, if it should change at all.
+    pub fn follow(&self, container: (f64, f64), canvas: (f64, f64)) -> Option<(f64, f64)> {
+        let (width, height) = container;
+        // A hidden container has no size to follow
+        if !self.enabled || width <= 0.0 || height <= 0.0 || container == canvas {
+            return None;
+        }
+        Some(container)
+    }
+}
+
 impl From<Canvas> for Rc<RefCell<Canvas>> {
     fn from(canvas: Canvas) -> Self {
         Rc::new(RefCell::new(canvas))
     }
 }
 
+/// Redraws the changed parts of the canvas in the animation frame after the layers changed, and
+/// tells the layer change callback when they did. Nothing runs while nothing changes.
+pub struct RenderLoop {
+    frame: Closure<dyn FnMut()>,
+    /// The requested animation frame, until it runs.
+    request: Rc<Cell<Option<i32>>>,
+}
+
+impl RenderLoop {
+    fn new(state: &State) -> Self {
+        let canvas = state.canvas.clone();
+        let layers = state.layers.clone();
+        let on_layers_change = state.on_layers_change.clone();
+        let request = Rc::new(Cell::new(None));
+        let frame = Closure::new(render_frame(canvas, layers, on_layers_change, &request));
+        RenderLoop { frame, request }
+    }
+
+    /// Asks for a frame, unless one is coming already.
+    pub fn schedule(&self) {
+        if self.request.get().is_none() {
+            let request = window()
+                .expect("Window not found")
+                .request_animation_frame(self.frame.as_ref().unchecked_ref())
+                .unwrap();
+            self.request.set(Some(request));
+        }
+    }
+
+    /// Drops the frame asked for, if any.
+    pub fn cancel(&self) {
+        if let Some(request) = self.request.take() {
+            window()
+                .expect("Window not found")
+                .cancel_animation_frame(request)
+                .unwrap();
+        }
+    }
+}
+
+impl Drop for RenderLoop {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// Renders the first frame and every one after a change of the layers.
+pub fn start_render_loop(state: &mut State) {
+    let render_loop = Rc::new(RenderLoop::new(state));
+    let weak = Rc::downgrade(&render_loop);
+    state.layers.borrow_mut().set_on_change(move || {
+        if let Some(render_loop) = weak.upgrade() {
+            render_loop.schedule();
+        }
+    });
+    state.render_loop = Some(render_loop);
+    state.schedule_render();
+}
+
+fn render_frame(
+    canvas: Rc<RefCell<Canvas>>,
+    layers: Rc<RefCell<Layers>>,
+    on_layers_change: Rc<RefCell<Option<Function>>>,
+    request: &Rc<Cell<Option<i32>>>,
+) -> impl FnMut() {
+    let request = request.clone();
+    move || {
+        request.set(None);
+        let damage = layers.borrow_mut().take_damage();
+        render_damage(&*canvas.borrow(), &layers.borrow(), damage);
+        // Nothing is borrowed during the call, so the callback can use the editor
+        let changed = layers.borrow_mut().take_layers_changed();
+        let callback = on_layers_change.borrow().clone().filter(|_| changed);
+        // A throwing callback must not keep the next frames from being drawn
+        if let Some(Err(err)) = callback.map(|callback| callback.call0(&JsValue::NULL)) {
+            console::error_1(&err);
+        }
+    }
+}
+
 fn init_canvas(canvas_id: &str) -> Result<HtmlCanvasElement, JsValue> {
     let canvas = window()
         .ok_or("Window not found")?
@@ -91,3 +314,23 @@ fn init_context(canvas: &HtmlCanvasElement) -> Result<CanvasRenderingContext2d,
 
     Ok(context)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_container_until_sized_explicitly() {
+        let mut auto_size = AutoSize::new();
+        let container = (600.0, 300.0);
+
+        let canvas = auto_size.follow(container, (800.0, 400.0)).unwrap();
+        assert_eq!(canvas, container);
+        // The container keeps its size, so resizing the canvas does not start another round
+        assert_eq!(auto_size.follow(container, canvas), None);
+        assert_eq!(auto_size.follow((0.0, 0.0), canvas), None);
+
+        auto_size.set_enabled(false);
+        assert_eq!(auto_size.follow((700.0, 300.0), canvas), None);
+    }
+}
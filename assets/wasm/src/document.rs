@@ -0,0 +1,389 @@
+use crate::events::Point;
+use crate::events::Transform;
+use crate::layers::Layer;
+use crate::layers::LayerId;
+use crate::layers::LayerMeta;
+use crate::layers::Layers;
+use crate::objects::Object;
+use crate::objects::ObjectData;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Current document schema version. Bump it together with a new entry in `MIGRATIONS`
+/// whenever the serialized form of a layer or object changes.
+pub const VERSION: u64 = 4;
+
+/// `MIGRATIONS[n]` upgrades a version `n + 1` document to version `n + 2`.
+const MIGRATIONS: [fn(Value) -> Value; VERSION as usize - 1] = [
+    add_rotation,
+    rotation_to_transform,
+    group_children_to_layers,
+];
+
+/// Layers are stored bottom to top, so their order in `layers` is the z-order.
+#[derive(Serialize, Deserialize)]
+struct Document {
+    version: u64,
+    layers: Vec<LayerData>,
+}
+
+/// A layer of the document or a child of a group. Names and flags are left out while they
+/// have their default value, so older versions of the schema read them as unnamed, shown and
+/// unlocked.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct LayerData {
+    id: u64,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    name: String,
+    #[serde(default, skip_serializing_if = "is_false")]
+    hidden: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    locked: bool,
+    object: ObjectData,
+}
+
+impl LayerData {
+    fn new(id: LayerId, meta: LayerMeta, object: ObjectData) -> Self {
+        let LayerMeta { name, hidden, locked } = meta;
+        LayerData { id, name, hidden, locked, object }
+    }
+}
+
+impl From<&Layer> for LayerData {
+    fn from(layer: &Layer) -> Self {
+        LayerData::new(layer.id, layer.meta.clone(), layer.object.to_data())
+    }
+}
+
+impl From<LayerData> for Layer {
+    fn from(layer: LayerData) -> Self {
+        Layer {
+            id: layer.id,
+            object: layer.object.into_object(),
+            meta: LayerMeta { name: layer.name, hidden: layer.hidden, locked: layer.locked },
+        }
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// A row of the layer panel.
+#[derive(Serialize)]
+struct LayerEntry<'a> {
+    id: LayerId,
+    name: &'a str,
+    kind: &'static str,
+    hidden: bool,
+    locked: bool,
+}
+
+pub fn to_json(layers: &Layers) -> String {
+    write(layers.document().iter().map(LayerData::from).collect())
+}
+
+/// Lists the layers being edited, bottom to top, with what a layer panel shows of them:
+/// `id`, `name`, object `kind` and the `hidden` and `locked` flags.
+pub fn layer_list_to_json(layers: &Layers) -> String {
+    let entries: Vec<_> = layers
+        .layers()
+        .iter()
+        .map(|layer| LayerEntry {
+            id: layer.id,
+            name: &layer.meta.name,
+            kind: layer.object.kind(),
+            hidden: layer.meta.hidden,
+            locked: layer.meta.locked,
+        })
+        .collect();
+    serde_json::to_string(&entries).unwrap()
+}
+
+/// Writes `objects` as a document of their own, e.g. for the clipboard. Their layers are
+/// numbered from 1, as pasting gives them new ids anyway.
+pub fn objects_to_json(objects: &[Box<dyn Object>]) -> String {
+    write(
+        objects
+            .iter()
+            .zip(1..)
+            .map(|(object, id)| LayerData::new(id, LayerMeta::default(), object.to_data()))
+            .collect(),
+    )
+}
+
+fn write(layers: Vec<LayerData>) -> String {
+    serde_json::to_string(&Document { version: VERSION, layers }).unwrap()
+}
+
+pub fn from_json(json: &str) -> Result<Vec<Layer>, String> {
+    let value: Value = serde_json::from_str(json).map_err(|err| err.to_string())?;
+    let document: Document =
+        serde_json::from_value(migrate(value)?).map_err(|err| err.to_string())?;
+
+    Ok(document.layers.into_iter().map(Layer::from).collect())
+}
+
+fn migrate(mut value: Value) -> Result<Value, String> {
+    let version = value
+        .get("version")
+        .and_then(Value::as_u64)
+        .ok_or("Document has no version")?;
+    if version == 0 || version > VERSION {
+        return Err(format!("Unsupported document version {}", version));
+    }
+
+    for migration in &MIGRATIONS[(version - 1) as usize..] {
+        value = migration(value);
+    }
+    value["version"] = VERSION.into();
+    Ok(value)
+}
+
+/// Version 2 added a rotation angle around the center of rects, ellipses and texts. Older
+/// ones were not rotated.
+fn add_rotation(mut value: Value) -> Value {
+    let Some(layers) = value["layers"].as_array_mut() else {
+        return value;
+    };
+    for layer in layers {
+        let Some(object) = layer["object"].as_object_mut() else {
+            continue;
+        };
+        let kind = object.get("kind").and_then(Value::as_str);
+        if matches!(kind, Some("rect" | "ellipse" | "text")) {
+            object.entry("rotation").or_insert(0.0.into());
+        }
+    }
+    value
+}
+
+/// Version 3 replaced the rotation angle around the center of rects, ellipses and texts
+/// with a general transform.
+fn rotation_to_transform(mut value: Value) -> Value {
+    let Some(layers) = value["layers"].as_array_mut() else {
+        return value;
+    };
+    for layer in layers {
+        let Some(object) = layer["object"].as_object_mut() else {
+            continue;
+        };
+        let rotation = object
+            .remove("rotation")
+            .and_then(|rotation| rotation.as_f64());
+        let Some(rotation) = rotation.filter(|&rotation| rotation != 0.0) else {
+            continue;
+        };
+        let field = |name: &str| object.get(name).and_then(Value::as_f64).unwrap_or(0.0);
+        let center =
+            Point { x: field("x") + field("width") / 2.0, y: field("y") + field("height") / 2.0 };
+        let transform = Transform::rotate(rotation).around(center);
+        object.insert(
+            "transform".to_string(),
+            serde_json::to_value(transform).unwrap(),
+        );
+    }
+    value
+}
+
+/// Version 4 stored the children of groups as layers, with ids and meta, instead of bare
+/// objects. They are numbered from 1 within their group.
+fn group_children_to_layers(mut value: Value) -> Value {
+    fn wrap_children(object: &mut Value) {
+        let Some(children) = object["children"].as_array_mut() else {
+            return;
+        };
+        for (child, id) in children.iter_mut().zip(1..) {
+            wrap_children(child);
+            let object = child.take();
+            *child = serde_json::json!({ "id": id, "object": object });
+        }
+    }
+
+    let Some(layers) = value["layers"].as_array_mut() else {
+        return value;
+    };
+    for layer in layers {
+        wrap_children(&mut layer["object"]);
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::ObjectKind;
+    use crate::style::Style;
+    use crate::surface::Recorder;
+    use crate::testing::draw;
+
+    #[test]
+    fn round_trips_layers_in_order() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = Layers::new();
+        draw(
+            &mut layers,
+            &surface,
+            ObjectKind::Rect,
+            (0.0, 0.0),
+            (10.0, 20.0),
+        );
+        draw(
+            &mut layers,
+            &surface,
+            ObjectKind::Line,
+            (5.0, 5.0),
+            (50.0, 0.0),
+        );
+
+        let json = to_json(&layers);
+        let loaded = from_json(&json).unwrap();
+
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[0].id, 1);
+        assert_eq!(loaded[0].object.bottom_right().y, 20.0);
+        assert_eq!(loaded[1].id, 2);
+        assert_eq!(loaded[1].object.right(), 50.0);
+    }
+
+    #[test]
+    fn keeps_layer_names_and_flags() {
+        let json = r#"{"version":4,"layers":[{"id":1,"name":"Sky","hidden":true,"object":{"kind":"rect","x":0.0,"y":0.0,"width":1.0,"height":1.0,"style":{"fill":null,"stroke":"black","stroke_width":1.0,"opacity":1.0,"dash":[]}}},{"id":2,"locked":true,"object":{"kind":"line","start":{"x":0.0,"y":0.0},"end":{"x":1.0,"y":1.0},"style":{"fill":null,"stroke":"black","stroke_width":1.0,"opacity":1.0,"dash":[]}}}]}"#;
+
+        let mut layers = Layers::new();
+        layers.load(from_json(json).unwrap());
+
+        assert_eq!(to_json(&layers), json);
+        assert_eq!(
+            layer_list_to_json(&layers),
+            r#"[{"id":1,"name":"Sky","kind":"rect","hidden":true,"locked":false},{"id":2,"name":"","kind":"line","hidden":false,"locked":true}]"#
+        );
+    }
+
+    #[test]
+    fn pastes_copied_objects_into_another_editor() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut source = Layers::new();
+        draw(
+            &mut source,
+            &surface,
+            ObjectKind::Ellipse,
+            (0.0, 0.0),
+            (10.0, 20.0),
+        );
+        source.copy_selection();
+        let json = objects_to_json(source.clipboard());
+
+        let mut target = Layers::new();
+        draw(
+            &mut target,
+            &surface,
+            ObjectKind::Rect,
+            (50.0, 50.0),
+            (60.0, 60.0),
+        );
+        let pasted = from_json(&json).unwrap();
+        target.paste(pasted.into_iter().map(|layer| layer.object).collect());
+
+        assert_eq!(target.layers().len(), 2);
+        assert_eq!(target.layers()[1].id, 2);
+        assert!(matches!(
+            target.layers()[1].object.to_data(),
+            ObjectData::Ellipse(_)
+        ));
+        assert_eq!(target.layers()[1].object.left(), 10.0);
+    }
+
+    #[test]
+    fn writes_kind_and_version() {
+        let json = r#"{"version":4,"layers":[{"id":7,"object":{"kind":"ellipse","x":1.0,"y":2.0,"width":3.0,"height":4.0,"style":{"fill":null,"stroke":"red","stroke_width":2.0,"opacity":0.5,"dash":[4.0,2.0]}}}]}"#;
+
+        let layers = from_json(json).unwrap();
+        let mut reloaded = Layers::new();
+        reloaded.load(layers);
+
+        assert_eq!(to_json(&reloaded), json);
+    }
+
+    #[test]
+    fn defaults_missing_style_per_kind() {
+        let json = r#"{"version":1,"layers":[
+            {"id":1,"object":{"kind":"rect","x":0.0,"y":0.0,"width":1.0,"height":1.0}},
+            {"id":2,"object":{"kind":"line","start":{"x":0.0,"y":0.0},"end":{"x":1.0,"y":1.0}}}
+        ]}"#;
+
+        let layers = from_json(json).unwrap();
+
+        assert_eq!(layers[0].object.style(), &Style::default());
+        assert_eq!(layers[1].object.style(), &Style::line());
+    }
+
+    #[test]
+    fn migrates_rotation_to_transform() {
+        let json = r#"{"version":2,"layers":[
+            {"id":1,"object":{"kind":"rect","x":0.0,"y":0.0,"width":20.0,"height":10.0,"rotation":1.5707963267948966}},
+            {"id":2,"object":{"kind":"text","x":0.0,"y":0.0,"width":20.0,"height":10.0,"rotation":0.0,"content":"a"}}
+        ]}"#;
+
+        let layers = from_json(json).unwrap();
+
+        let corner = layers[0].object.to_world(Point { x: 0.0, y: 0.0 });
+        assert!((corner.x - 15.0).abs() < 1e-9 && (corner.y + 5.0).abs() < 1e-9);
+        assert!(layers[1].object.transform().is_identity());
+    }
+
+    #[test]
+    fn reads_groups_with_children() {
+        let json = r#"{"version":3,"layers":[{"id":1,"object":{"kind":"group","children":[
+            {"kind":"rect","x":0.0,"y":0.0,"width":10.0,"height":10.0},
+            {"kind":"group","children":[{"kind":"ellipse","x":20.0,"y":0.0,"width":10.0,"height":30.0}]}
+        ]}}]}"#;
+
+        let layers = from_json(json).unwrap();
+
+        let group = layers[0].object.as_group().unwrap();
+        let ids: Vec<_> = group.children().iter().map(|child| child.id).collect();
+        assert_eq!(ids, vec![1, 2]);
+        assert_eq!(layers[0].object.bottom_right(), Point { x: 30.0, y: 30.0 });
+        assert!(from_json(
+            &json.replace("\"kind\":\"rect\"", "\"kind\":\"group\",\"children\":[]")
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn keeps_ids_names_and_flags_of_group_children() {
+        let json = r#"{"version":4,"layers":[{"id":1,"object":{"kind":"group","children":[
+            {"id":4,"name":"Left","hidden":true,"object":{"kind":"rect","x":0.0,"y":0.0,"width":10.0,"height":10.0}}
+        ]}}]}"#;
+        let mut layers = Layers::new();
+        layers.load(from_json(json).unwrap());
+
+        let loaded = from_json(&to_json(&layers)).unwrap();
+
+        let child = &loaded[0].object.as_group().unwrap().children()[0];
+        assert_eq!(child.id, 4);
+        assert_eq!(child.meta.name, "Left");
+        assert!(child.meta.hidden && !child.meta.locked);
+    }
+
+    #[test]
+    fn adds_rotation_to_version_1_objects() {
+        let value = serde_json::json!({"version": 1, "layers": [
+            {"id": 1, "object": {"kind": "rect"}},
+            {"id": 2, "object": {"kind": "line"}}
+        ]});
+
+        let value = add_rotation(value);
+
+        assert_eq!(value["layers"][0]["object"]["rotation"], 0.0);
+        assert!(value["layers"][1]["object"].get("rotation").is_none());
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        assert!(from_json(r#"{"version":5,"layers":[]}"#).is_err());
+        assert!(from_json(r#"{"layers":[]}"#).is_err());
+    }
+}
@@ -1,9 +1,21 @@
+use crate::document;
+use crate::shortcuts::Shortcut;
 use crate::state::State;
+use crate::surface::Surface;
+use js_sys::Array;
+use serde::Deserialize;
+use serde::Serialize;
 use std::cell::RefCell;
 use std::rc::Rc;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
+use web_sys::window;
+use web_sys::ClipboardEvent;
+use web_sys::KeyboardEvent;
 use web_sys::MouseEvent;
+use web_sys::ResizeObserver;
+use web_sys::ResizeObserverEntry;
+use web_sys::WheelEvent;
 
 macro_rules! closure {
     ( { $($x:ident),* }, $y:expr ) => {
@@ -14,29 +26,210 @@ macro_rules! closure {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Point {
     pub x: f64,
     pub y: f64,
 }
 
 impl Point {
+    pub fn add(&self, point: Point) -> Point {
+        Point { x: self.x + point.x, y: self.y + point.y }
+    }
+
     pub fn subtract(&self, point: Point) -> Point {
         Point { x: self.x - point.x, y: self.y - point.y }
     }
+
+    pub fn scale(&self, factor: f64) -> Point {
+        Point { x: self.x * factor, y: self.y * factor }
+    }
+
+    /// Angle of the vector from the origin to this point, in radians clockwise from the x axis.
+    pub fn angle(&self) -> f64 {
+        self.y.atan2(self.x)
+    }
+}
+
+/// 2D affine transform mapping `(x, y)` to `(a * x + c * y + e, b * x + d * y + f)`, the
+/// same layout as the canvas `transform` call and SVG `matrix`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Transform {
+    pub a: f64,
+    pub b: f64,
+    pub c: f64,
+    pub d: f64,
+    pub e: f64,
+    pub f: f64,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::IDENTITY
+    }
 }
 
-#[allow(clippy::enum_variant_names)]
+impl Transform {
+    pub const IDENTITY: Transform = Transform { a: 1.0, b: 0.0, c: 0.0, d: 1.0, e: 0.0, f: 0.0 };
+
+    pub fn translate(x: f64, y: f64) -> Transform {
+        Transform { e: x, f: y, ..Transform::IDENTITY }
+    }
+
+    pub fn scale(x: f64, y: f64) -> Transform {
+        Transform { a: x, d: y, ..Transform::IDENTITY }
+    }
+
+    /// Clockwise rotation by `angle` radians around the origin.
+    pub fn rotate(angle: f64) -> Transform {
+        let (sin, cos) = angle.sin_cos();
+        Transform { a: cos, b: sin, c: -sin, d: cos, ..Transform::IDENTITY }
+    }
+
+    /// Shears by the angles `x` and `y`, in radians, like the SVG `skewX` and `skewY`.
+    pub fn skew(x: f64, y: f64) -> Transform {
+        Transform { b: y.tan(), c: x.tan(), ..Transform::IDENTITY }
+    }
+
+    /// This transform followed by `next`.
+    pub fn then(&self, next: &Transform) -> Transform {
+        Transform {
+            a: next.a * self.a + next.c * self.b,
+            b: next.b * self.a + next.d * self.b,
+            c: next.a * self.c + next.c * self.d,
+            d: next.b * self.c + next.d * self.d,
+            e: next.a * self.e + next.c * self.f + next.e,
+            f: next.b * self.e + next.d * self.f + next.f,
+        }
+    }
+
+    /// The same transform with `center` as its origin instead of (0, 0).
+    pub fn around(&self, center: Point) -> Transform {
+        Transform::translate(-center.x, -center.y)
+            .then(self)
+            .then(&Transform::translate(center.x, center.y))
+    }
+
+    pub fn invert(&self) -> Option<Transform> {
+        let det = self.a * self.d - self.b * self.c;
+        if det == 0.0 {
+            return None;
+        }
+        Some(Transform {
+            a: self.d / det,
+            b: -self.b / det,
+            c: -self.c / det,
+            d: self.a / det,
+            e: (self.c * self.f - self.d * self.e) / det,
+            f: (self.b * self.e - self.a * self.f) / det,
+        })
+    }
+
+    pub fn apply(&self, point: Point) -> Point {
+        Point {
+            x: self.a * point.x + self.c * point.y + self.e,
+            y: self.b * point.x + self.d * point.y + self.f,
+        }
+    }
+
+    /// Applies the transform to a direction or offset, i.e. without the translation.
+    pub fn apply_vector(&self, vector: Point) -> Point {
+        Point {
+            x: self.a * vector.x + self.c * vector.y,
+            y: self.b * vector.x + self.d * vector.y,
+        }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Transform::IDENTITY
+    }
+
+    /// Whether the transform only moves and stretches, without turning, shearing or flipping.
+    pub fn is_axis_aligned(&self) -> bool {
+        self.b == 0.0 && self.c == 0.0 && self.a > 0.0 && self.d > 0.0
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub meta: bool,
+}
+
+impl From<&MouseEvent> for Modifiers {
+    fn from(event: &MouseEvent) -> Self {
+        Modifiers {
+            shift: event.shift_key(),
+            ctrl: event.ctrl_key(),
+            alt: event.alt_key(),
+            meta: event.meta_key(),
+        }
+    }
+}
+
+impl From<&KeyboardEvent> for Modifiers {
+    fn from(event: &KeyboardEvent) -> Self {
+        Modifiers {
+            shift: event.shift_key(),
+            ctrl: event.ctrl_key(),
+            alt: event.alt_key(),
+            meta: event.meta_key(),
+        }
+    }
+}
+
+/// Mouse and wheel points are in canvas pixels; `Layers` maps them into the document.
 pub enum Event {
-    MouseDown { point: Point },
-    MouseMove { point: Point },
-    MouseUp { point: Point },
+    MouseDown {
+        point: Point,
+        modifiers: Modifiers,
+    },
+    MouseMove {
+        point: Point,
+        modifiers: Modifiers,
+    },
+    MouseUp {
+        point: Point,
+        modifiers: Modifiers,
+    },
+    /// Follows the second `MouseUp` of a double click.
+    DoubleClick {
+        point: Point,
+        modifiers: Modifiers,
+    },
+    /// `delta` is the vertical scroll distance in pixels, positive when scrolling down.
+    Wheel {
+        point: Point,
+        delta: f64,
+        modifiers: Modifiers,
+    },
+    /// `key` is the DOM `KeyboardEvent.key` value, e.g. "a", "Delete" or "ArrowLeft".
+    KeyDown {
+        key: String,
+        modifiers: Modifiers,
+    },
+    KeyUp {
+        key: String,
+        modifiers: Modifiers,
+    },
 }
 
 pub fn init(state_ref: &Rc<RefCell<State>>) {
     let on_mousedown = on_mousedown(state_ref);
     let on_mouseup = on_mouseup(state_ref);
     let on_mousemove = on_mousemove(state_ref);
+    let on_dblclick = on_dblclick(state_ref);
+    let on_wheel = on_wheel(state_ref);
+    let on_keydown = on_keydown(state_ref);
+    let on_keyup = on_keyup(state_ref);
+    let on_blur = on_blur(state_ref);
+    let on_resize = on_resize(state_ref);
+    let on_cut = on_copy(state_ref, true);
+    let on_copy = on_copy(state_ref, false);
+    let on_paste = on_paste(state_ref);
+    let on_pixel_ratio_change = on_pixel_ratio_change(state_ref);
 
     let mut state = state_ref.borrow_mut();
 
@@ -44,8 +237,74 @@ pub fn init(state_ref: &Rc<RefCell<State>>) {
     canvas.set_onmousedown(Some(on_mousedown.as_ref().unchecked_ref()));
     canvas.set_onmouseup(Some(on_mouseup.as_ref().unchecked_ref()));
     canvas.set_onmousemove(Some(on_mousemove.as_ref().unchecked_ref()));
+    canvas.set_ondblclick(Some(on_dblclick.as_ref().unchecked_ref()));
+    canvas.set_onwheel(Some(on_wheel.as_ref().unchecked_ref()));
+
+    // Keys go to the editor only while the canvas has the focus, which clicking it gives it, so
+    // that the rest of the page keeps its own keyboard behavior
+    canvas.set_tab_index(0);
+    canvas.set_onkeydown(Some(on_keydown.as_ref().unchecked_ref()));
+    canvas.set_onkeyup(Some(on_keyup.as_ref().unchecked_ref()));
+    canvas.set_onblur(Some(on_blur.as_ref().unchecked_ref()));
+
+    // Ctrl+C, Ctrl+X and Ctrl+V arrive as these rather than as shortcuts, as only clipboard
+    // events may use the system clipboard without asking. Like keys, they go to the canvas
+    // only while it has the focus.
+    canvas.set_oncopy(Some(on_copy.as_ref().unchecked_ref()));
+    canvas.set_oncut(Some(on_cut.as_ref().unchecked_ref()));
+    canvas.set_onpaste(Some(on_paste.as_ref().unchecked_ref()));
 
-    state._closuers = vec![on_mousedown, on_mouseup, on_mousemove];
+    // The canvas takes the size of the element around it, see `AutoSize`
+    if let Some(container) = canvas.parent_element() {
+        let observer = ResizeObserver::new(on_resize.as_ref().unchecked_ref()).unwrap();
+        observer.observe(&container);
+        state.resize_observer = Some(observer);
+    }
+
+    state._closuers = vec![on_mousedown, on_mouseup, on_mousemove, on_dblclick];
+    state._key_closures = vec![on_keydown, on_keyup];
+    state._blur_closure = Some(on_blur);
+    state._wheel_closure = Some(on_wheel);
+    state._resize_closure = Some(on_resize);
+    state._clipboard_closures = vec![on_copy, on_cut, on_paste];
+    state._pixel_ratio_closure = Some(on_pixel_ratio_change);
+    drop(state);
+    watch_pixel_ratio(state_ref);
+}
+
+/// Listens for the `devicePixelRatio` to change from what it is now, e.g. when the window
+/// moves to another display or the page is zoomed.
+fn watch_pixel_ratio(state: &Rc<RefCell<State>>) {
+    let mut state = state.borrow_mut();
+    let state = &mut *state;
+    let window = window().expect("Window not found");
+    let query = format!("(resolution: {}dppx)", window.device_pixel_ratio());
+    let Ok(Some(list)) = window.match_media(&query) else {
+        return;
+    };
+    let on_change = state._pixel_ratio_closure.as_ref().unwrap();
+    if let Some(old) = state.pixel_ratio_query.take() {
+        old.remove_event_listener_with_callback("change", on_change.as_ref().unchecked_ref())
+            .unwrap();
+    }
+    list.add_event_listener_with_callback("change", on_change.as_ref().unchecked_ref())
+        .unwrap();
+    state.pixel_ratio_query = Some(list);
+}
+
+/// Sizes the backing store for the new pixel ratio, so that drawings stay sharp.
+fn on_pixel_ratio_change(state: &Rc<RefCell<State>>) -> Closure<dyn FnMut()> {
+    closure!({ state }, move || {
+        {
+            let state = state.borrow();
+            let (width, height) = {
+                let canvas = state.canvas.borrow();
+                (canvas.width(), canvas.height())
+            };
+            state.resize(width, height).unwrap();
+        }
+        watch_pixel_ratio(&state);
+    })
 }
 
 fn on_mousedown(state: &Rc<RefCell<State>>) -> Closure<dyn FnMut(MouseEvent)> {
@@ -53,10 +312,11 @@ fn on_mousedown(state: &Rc<RefCell<State>>) -> Closure<dyn FnMut(MouseEvent)> {
         let state = state.borrow();
         let canvas = state.canvas.borrow();
         let point = canvas.get_mouse_position(&event);
+        let modifiers = Modifiers::from(&event);
         state
             .layers
             .borrow_mut()
-            .on_event(Event::MouseDown { point }, &canvas);
+            .on_event(Event::MouseDown { point, modifiers }, &*canvas);
     })
 }
 
@@ -65,10 +325,11 @@ fn on_mousemove(state: &Rc<RefCell<State>>) -> Closure<dyn FnMut(MouseEvent)> {
         let state = state.borrow();
         let canvas = state.canvas.borrow();
         let point = canvas.get_mouse_position(&event);
+        let modifiers = Modifiers::from(&event);
         state
             .layers
             .borrow_mut()
-            .on_event(Event::MouseMove { point }, &canvas);
+            .on_event(Event::MouseMove { point, modifiers }, &*canvas);
     })
 }
 
@@ -77,9 +338,188 @@ fn on_mouseup(state: &Rc<RefCell<State>>) -> Closure<dyn FnMut(MouseEvent)> {
         let state = state.borrow();
         let canvas = state.canvas.borrow();
         let point = canvas.get_mouse_position(&event);
+        let modifiers = Modifiers::from(&event);
+        state
+            .layers
+            .borrow_mut()
+            .on_event(Event::MouseUp { point, modifiers }, &*canvas);
+    })
+}
+
+fn on_dblclick(state: &Rc<RefCell<State>>) -> Closure<dyn FnMut(MouseEvent)> {
+    closure!({ state }, move |event: MouseEvent| {
+        let state = state.borrow();
+        let canvas = state.canvas.borrow();
+        let point = canvas.get_mouse_position(&event);
+        let modifiers = Modifiers::from(&event);
+        state
+            .layers
+            .borrow_mut()
+            .on_event(Event::DoubleClick { point, modifiers }, &*canvas);
+    })
+}
+
+fn on_wheel(state: &Rc<RefCell<State>>) -> Closure<dyn FnMut(WheelEvent)> {
+    closure!({ state }, move |event: WheelEvent| {
+        // Keep the page from scrolling while zooming the canvas
+        event.prevent_default();
+
+        let state = state.borrow();
+        let canvas = state.canvas.borrow();
+        let point = canvas.get_mouse_position(&event);
+        let delta = event.delta_y()
+            * match event.delta_mode() {
+                WheelEvent::DOM_DELTA_LINE => 16.0,
+                WheelEvent::DOM_DELTA_PAGE => canvas.height(),
+                _ => 1.0,
+            };
+        let modifiers = Modifiers::from(&*event);
+        state
+            .layers
+            .borrow_mut()
+            .on_event(Event::Wheel { point, delta, modifiers }, &*canvas);
+    })
+}
+
+fn on_keydown(state: &Rc<RefCell<State>>) -> Closure<dyn FnMut(KeyboardEvent)> {
+    closure!({ state }, move |event: KeyboardEvent| {
+        let key = event.key();
+        let modifiers = Modifiers::from(&event);
+        if Shortcut::from_key(&key, modifiers).is_some() {
+            event.prevent_default();
+        }
+
+        let state = state.borrow();
+        let canvas = state.canvas.borrow();
+        state
+            .layers
+            .borrow_mut()
+            .on_event(Event::KeyDown { key, modifiers }, &*canvas);
+    })
+}
+
+fn on_keyup(state: &Rc<RefCell<State>>) -> Closure<dyn FnMut(KeyboardEvent)> {
+    closure!({ state }, move |event: KeyboardEvent| {
+        let key = event.key();
+        let modifiers = Modifiers::from(&event);
+
+        let state = state.borrow();
+        let canvas = state.canvas.borrow();
         state
             .layers
             .borrow_mut()
-            .on_event(Event::MouseUp { point }, &canvas);
+            .on_event(Event::KeyUp { key, modifiers }, &*canvas);
     })
 }
+
+/// Keys released after the canvas lost the focus never reach it, so Space stops panning here.
+fn on_blur(state: &Rc<RefCell<State>>) -> Closure<dyn FnMut()> {
+    closure!({ state }, move || {
+        let key = " ".to_string();
+        let modifiers = Modifiers::default();
+
+        let state = state.borrow();
+        let canvas = state.canvas.borrow();
+        state
+            .layers
+            .borrow_mut()
+            .on_event(Event::KeyUp { key, modifiers }, &*canvas);
+    })
+}
+
+/// Puts the selected layers on the system clipboard as a JSON document, which other editors
+/// can paste. With `cut` they are removed as well.
+fn on_copy(state: &Rc<RefCell<State>>, cut: bool) -> Closure<dyn FnMut(ClipboardEvent)> {
+    closure!({ state }, move |event: ClipboardEvent| {
+        let state = state.borrow();
+        let mut layers = state.layers.borrow_mut();
+        if layers.selection().is_empty() {
+            return;
+        }
+        let Some(data) = event.clipboard_data() else {
+            return;
+        };
+        layers.copy_selection();
+        let json = document::objects_to_json(layers.clipboard());
+        data.set_data("text/plain", &json).unwrap();
+        event.prevent_default();
+        if cut {
+            layers.delete_selection();
+        }
+    })
+}
+
+fn on_paste(state: &Rc<RefCell<State>>) -> Closure<dyn FnMut(ClipboardEvent)> {
+    closure!({ state }, move |event: ClipboardEvent| {
+        let Some(json) = event
+            .clipboard_data()
+            .and_then(|data| data.get_data("text/plain").ok())
+        else {
+            return;
+        };
+        // Anything but a document was not copied from an editor
+        let Ok(pasted) = document::from_json(&json) else {
+            return;
+        };
+        event.prevent_default();
+        let objects = pasted.into_iter().map(|layer| layer.object).collect();
+        state.borrow().layers.borrow_mut().paste(objects);
+    })
+}
+
+fn on_resize(state: &Rc<RefCell<State>>) -> Closure<dyn FnMut(Array)> {
+    closure!({ state }, move |entries: Array| {
+        let Some(entry) = entries.iter().last() else {
+            return;
+        };
+        let rect = entry.unchecked_into::<ResizeObserverEntry>().content_rect();
+        let state = state.borrow();
+        let canvas = {
+            let canvas = state.canvas.borrow();
+            (canvas.width(), canvas.height())
+        };
+        let container = (rect.width(), rect.height());
+        if let Some((width, height)) = state.auto_size.follow(container, canvas) {
+            state.resize(width, height).unwrap();
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    fn assert_near(a: Point, b: Point) {
+        assert!(
+            (a.x - b.x).abs() < 1e-9 && (a.y - b.y).abs() < 1e-9,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn composes_transforms_in_order() {
+        let transform = Transform::scale(2.0, 1.0).then(&Transform::translate(10.0, 0.0));
+
+        assert_near(
+            transform.apply(Point { x: 1.0, y: 1.0 }),
+            Point { x: 12.0, y: 1.0 },
+        );
+    }
+
+    #[test]
+    fn rotates_around_center_and_inverts() {
+        let center = Point { x: 10.0, y: 10.0 };
+        let transform = Transform::rotate(PI / 2.0).around(center);
+        let point = transform.apply(Point { x: 20.0, y: 10.0 });
+
+        assert_near(point, Point { x: 10.0, y: 20.0 });
+        assert_near(
+            transform.invert().unwrap().apply(point),
+            Point { x: 20.0, y: 10.0 },
+        );
+        assert!(Transform::scale(0.0, 1.0).invert().is_none());
+    }
+}
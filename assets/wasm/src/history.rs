@@ -0,0 +1,229 @@
+use crate::layers::Layer;
+use crate::layers::LayerId;
+use crate::layers::LayerMeta;
+use crate::layers::LayerStack;
+use crate::objects::Object;
+
+/// A change to the layer stack. Layers are referred to by id, except for where in the stack
+/// a created or deleted layer goes.
+pub enum Command {
+    Create {
+        position: usize,
+        id: LayerId,
+        object: Box<dyn Object>,
+        meta: LayerMeta,
+    },
+    Modify {
+        id: LayerId,
+        before: Box<dyn Object>,
+        after: Box<dyn Object>,
+    },
+    Delete {
+        position: usize,
+        id: LayerId,
+        object: Box<dyn Object>,
+        meta: LayerMeta,
+    },
+    /// Renames, hides, shows, locks or unlocks a layer.
+    Meta {
+        id: LayerId,
+        before: LayerMeta,
+        after: LayerMeta,
+    },
+    /// Moves a layer from one place in the stack to another.
+    Reorder { id: LayerId, from: usize, to: usize },
+    /// Several commands applied as one step, e.g. moving a multi-selection.
+    Batch(Vec<Command>),
+}
+
+impl Command {
+    fn undo(&self, layers: &mut LayerStack) -> Vec<LayerId> {
+        match self {
+            Command::Create { id, .. } => {
+                layers.remove(*id);
+                Vec::new()
+            }
+            Command::Modify { id, before, .. } => {
+                layers.layer_mut(*id).object = before.clone();
+                vec![*id]
+            }
+            Command::Delete { position, id, object, meta } => {
+                let layer = Layer { id: *id, object: object.clone(), meta: meta.clone() };
+                layers.insert(*position, layer);
+                vec![*id]
+            }
+            Command::Meta { id, before, .. } => {
+                layers.layer_mut(*id).meta = before.clone();
+                vec![*id]
+            }
+            Command::Reorder { id, from, .. } => {
+                layers.move_to(*id, *from);
+                vec![*id]
+            }
+            Command::Batch(commands) => commands
+                .iter()
+                .rev()
+                .flat_map(|command| command.undo(layers))
+                .collect(),
+        }
+    }
+
+    fn redo(&self, layers: &mut LayerStack) -> Vec<LayerId> {
+        match self {
+            Command::Create { position, id, object, meta } => {
+                let layer = Layer { id: *id, object: object.clone(), meta: meta.clone() };
+                layers.insert(*position, layer);
+                vec![*id]
+            }
+            Command::Modify { id, after, .. } => {
+                layers.layer_mut(*id).object = after.clone();
+                vec![*id]
+            }
+            Command::Delete { id, .. } => {
+                layers.remove(*id);
+                Vec::new()
+            }
+            Command::Meta { id, after, .. } => {
+                layers.layer_mut(*id).meta = after.clone();
+                vec![*id]
+            }
+            Command::Reorder { id, to, .. } => {
+                layers.move_to(*id, *to);
+                vec![*id]
+            }
+            Command::Batch(commands) => commands
+                .iter()
+                .flat_map(|command| command.redo(layers))
+                .collect(),
+        }
+    }
+}
+
+pub struct History {
+    undo_stack: Vec<Command>,
+    redo_stack: Vec<Command>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        History { undo_stack: Vec::new(), redo_stack: Vec::new() }
+    }
+
+    pub fn push(&mut self, command: Command) {
+        self.undo_stack.push(command);
+        self.redo_stack.clear();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    /// Reverts the last command. Returns the layers that should become selected.
+    pub fn undo(&mut self, layers: &mut LayerStack) -> Vec<LayerId> {
+        let Some(command) = self.undo_stack.pop() else {
+            return Vec::new();
+        };
+        let selection = command.undo(layers);
+        self.redo_stack.push(command);
+        selection
+    }
+
+    /// Reapplies the last undone command. Returns the layers that should become selected.
+    pub fn redo(&mut self, layers: &mut LayerStack) -> Vec<LayerId> {
+        let Some(command) = self.redo_stack.pop() else {
+            return Vec::new();
+        };
+        let selection = command.redo(layers);
+        self.undo_stack.push(command);
+        selection
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::Point;
+    use crate::objects::Rect;
+
+    #[test]
+    fn undoes_and_redoes_create() {
+        let object: Box<dyn Object> = Box::new(Rect::new(
+            Point { x: 0.0, y: 0.0 },
+            Point { x: 10.0, y: 10.0 },
+        ));
+        let mut layers = LayerStack::from(vec![Layer::new(1, object.clone())]);
+        let mut history = History::new();
+        let meta = LayerMeta::default();
+        history.push(Command::Create { position: 0, id: 1, object, meta });
+
+        assert!(history.undo(&mut layers).is_empty());
+        assert!(layers.is_empty());
+        assert_eq!(history.redo(&mut layers), vec![1]);
+        assert_eq!(layers.len(), 1);
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn undoes_and_redoes_modify() {
+        let before: Box<dyn Object> = Box::new(Rect::new(
+            Point { x: 0.0, y: 0.0 },
+            Point { x: 10.0, y: 10.0 },
+        ));
+        let mut after = before.clone();
+        after.translate(Point { x: 5.0, y: 5.0 });
+        let mut layers = LayerStack::from(vec![Layer::new(1, after.clone())]);
+        let mut history = History::new();
+        history.push(Command::Modify { id: 1, before, after });
+
+        history.undo(&mut layers);
+        assert_eq!(layers[0].object.left(), 0.0);
+        history.redo(&mut layers);
+        assert_eq!(layers[0].object.left(), 5.0);
+    }
+
+    #[test]
+    fn push_clears_redo_stack() {
+        let object: Box<dyn Object> = Box::new(Rect::new(
+            Point { x: 0.0, y: 0.0 },
+            Point { x: 10.0, y: 10.0 },
+        ));
+        let mut layers = LayerStack::from(vec![Layer::new(1, object.clone())]);
+        let mut history = History::new();
+        let meta = LayerMeta::default();
+        let create = |object| Command::Create { position: 0, id: 1, object, meta: meta.clone() };
+        history.push(create(object.clone()));
+        history.undo(&mut layers);
+        assert!(history.can_redo());
+
+        history.push(create(object));
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn undoes_batch_as_one_step() {
+        let object: Box<dyn Object> = Box::new(Rect::new(
+            Point { x: 0.0, y: 0.0 },
+            Point { x: 10.0, y: 10.0 },
+        ));
+        let mut moved = object.clone();
+        moved.translate(Point { x: 5.0, y: 5.0 });
+        let mut layers = LayerStack::from(vec![
+            Layer::new(1, moved.clone()),
+            Layer::new(2, moved.clone()),
+        ]);
+        let mut history = History::new();
+        history.push(Command::Batch(vec![
+            Command::Modify { id: 1, before: object.clone(), after: moved.clone() },
+            Command::Modify { id: 2, before: object, after: moved },
+        ]));
+
+        assert_eq!(history.undo(&mut layers), vec![2, 1]);
+        assert_eq!(layers[0].object.left(), 0.0);
+        assert_eq!(layers[1].object.left(), 0.0);
+        assert!(!history.can_undo());
+    }
+}
@@ -1,173 +1,1380 @@
-use crate::canvas::Canvas;
 use crate::events::Event;
+use crate::events::Modifiers;
 use crate::events::Point;
-
+use crate::events::Transform;
+use crate::history::Command;
+use crate::history::History;
 use crate::objects::Edge;
+use crate::objects::Group;
 use crate::objects::Object;
+use crate::objects::ObjectKind;
 use crate::objects::Rect;
+use crate::objects::Text;
+use crate::renderer::Damage;
+use crate::shortcuts::Shortcut;
+use crate::spatial::SpatialIndex;
+use crate::style::Style;
+use crate::surface::Surface;
+use crate::tools::Tool;
+use crate::viewport::Viewport;
 use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::ops::Deref;
 use std::rc::Rc;
 
+/// How far outside a shape, in screen pixels, a point still counts as over it.
+const HIT_TOLERANCE: f64 = 1.0;
+/// Rotation snaps to multiples of 15° while Shift is held.
+const ROTATION_SNAP: f64 = PI / 12.0;
+/// Each pixel scrolled with the wheel zooms by this factor.
+const WHEEL_ZOOM_STEP: f64 = 1.002;
+/// Canvas pixels around a layer that its outline and handles can paint over.
+const OVERLAY_MARGIN: f64 = 40.0;
+/// How far right and down from the originals duplicated and pasted layers are placed, and
+/// from the previous copy when the same objects are pasted again.
+const PASTE_OFFSET: f64 = 10.0;
+
+/// Identifies a layer for as long as it exists, wherever it moves in the stack.
+pub type LayerId = u64;
+
+#[derive(Clone)]
 pub struct Layer {
+    pub id: LayerId,
     pub object: Box<dyn Object>,
+    pub meta: LayerMeta,
 }
 
 impl Layer {
-    pub fn point_over_edge(&self, canvas: &Canvas, point: Point) -> Option<Edge> {
-        let context = canvas.context();
-        self.object
-            .edges()
-            .into_iter()
-            .find(|edge| edge.is_point_over(context, point))
+    /// A layer with no name that is shown and unlocked.
+    pub fn new(id: LayerId, object: Box<dyn Object>) -> Self {
+        Layer { id, object, meta: LayerMeta::default() }
+    }
+
+    /// Whether the layer can be hit and selected on the canvas.
+    fn selectable(&self) -> bool {
+        !self.meta.hidden && !self.meta.locked
+    }
+}
+
+/// What a layer panel shows about a layer besides its object.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LayerMeta {
+    /// Given by the user; empty until then.
+    pub name: String,
+    /// Hidden layers are not drawn.
+    pub hidden: bool,
+    /// Locked layers are drawn, but cannot be hit or selected on the canvas.
+    pub locked: bool,
+}
+
+/// Ways of moving the selected layers up or down the stack.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reorder {
+    /// One step up, past the next unselected layer above.
+    Forward,
+    /// One step down, past the next unselected layer below.
+    Backward,
+    ToFront,
+    ToBack,
+}
+
+/// Layers bottom to top, along with where each id is in the stack.
+pub struct LayerStack {
+    layers: Vec<Layer>,
+    positions: HashMap<LayerId, usize>,
+}
+
+impl LayerStack {
+    pub fn new() -> Self {
+        LayerStack { layers: Vec::new(), positions: HashMap::new() }
+    }
+
+    pub fn position(&self, id: LayerId) -> Option<usize> {
+        self.positions.get(&id).copied()
+    }
+
+    pub fn get(&self, id: LayerId) -> Option<&Layer> {
+        self.position(id).map(|position| &self.layers[position])
+    }
+
+    /// The layer with `id`, which must be in the stack.
+    pub fn layer(&self, id: LayerId) -> &Layer {
+        &self.layers[self.positions[&id]]
+    }
+
+    pub fn layer_mut(&mut self, id: LayerId) -> &mut Layer {
+        &mut self.layers[self.positions[&id]]
+    }
+
+    pub fn push(&mut self, layer: Layer) {
+        self.insert(self.layers.len(), layer);
+    }
+
+    /// Inserts `layer` at `position`, moving the ones above it up.
+    pub fn insert(&mut self, position: usize, layer: Layer) {
+        self.layers.insert(position, layer);
+        self.renumber(position);
+    }
+
+    /// Takes the layer with `id`, which must be in the stack, out. Returns where it was along
+    /// with it.
+    pub fn remove(&mut self, id: LayerId) -> (usize, Layer) {
+        let position = self.positions.remove(&id).unwrap();
+        let layer = self.layers.remove(position);
+        self.renumber(position);
+        (position, layer)
+    }
+
+    /// Moves the layer with `id`, which must be in the stack, to `position`. The layers in
+    /// between shift by one to make room.
+    pub fn move_to(&mut self, id: LayerId, position: usize) {
+        let (_, layer) = self.remove(id);
+        self.insert(position, layer);
+    }
+
+    /// Records the positions of the layers from `start` up.
+    fn renumber(&mut self, start: usize) {
+        for (position, layer) in self.layers.iter().enumerate().skip(start) {
+            self.positions.insert(layer.id, position);
+        }
+    }
+}
+
+impl From<Vec<Layer>> for LayerStack {
+    fn from(layers: Vec<Layer>) -> Self {
+        let mut stack = LayerStack { layers, positions: HashMap::new() };
+        stack.renumber(0);
+        stack
+    }
+}
+
+impl Deref for LayerStack {
+    type Target = [Layer];
+
+    fn deref(&self) -> &[Layer] {
+        &self.layers
+    }
+}
+
+impl<'a> IntoIterator for &'a LayerStack {
+    type Item = &'a Layer;
+    type IntoIter = std::slice::Iter<'a, Layer>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.layers.iter()
     }
 }
 
 pub enum LayerState {
-    ToCreate { start: Point },
-    Creating { layer: usize, start: Point },
-    Idle { layer: usize },
-    Resize { layer: usize, edge: Edge },
-    Relocate { layer: usize, grab_point: Point },
+    ToCreate {
+        kind: ObjectKind,
+        start: Point,
+    },
+    Creating {
+        kind: ObjectKind,
+        layer: LayerId,
+        start: Point,
+    },
+    Resize {
+        edge: Edge,
+        /// The handle as it was pressed, which Shift and Alt resizes keep measuring from.
+        grabbed: Edge,
+    },
+    Relocate {
+        origin: Point,
+    },
+    Rotate {
+        center: Point,
+        start_angle: f64,
+    },
+    Marquee {
+        start: Point,
+        end: Point,
+        additive: bool,
+    },
+    /// Scrolling the view so that the document point `origin` stays under the pointer.
+    Pan {
+        origin: Point,
+    },
+}
+
+/// The level a group was entered from, put aside while its children are edited.
+struct Outside {
+    layers: LayerStack,
+    history: History,
+    index: SpatialIndex,
+    /// The entered group, which is kept in step with the layers being edited.
+    group: LayerId,
+    /// The group as it was when entered.
+    before: Box<dyn Object>,
 }
 
 pub struct Layers {
-    layers: Vec<Layer>,
-    active_layer: Option<LayerState>,
-    outlined_layer: Option<usize>,
+    /// The layers being edited: the document, or the children of the entered group.
+    layers: LayerStack,
+    selection: Vec<LayerId>,
+    state: Option<LayerState>,
+    outlined_layer: Option<LayerId>,
+    tool: Tool,
+    history: History,
+    snapshots: Vec<(LayerId, Box<dyn Object>)>,
+    last_id: u64,
+    viewport: Viewport,
+    pan_key_held: bool,
+    damage: Damage,
+    index: SpatialIndex,
+    /// Copied objects, bottom to top.
+    clipboard: Vec<Box<dyn Object>>,
+    /// Where the objects pasted last were copied from, and how far they were moved from there.
+    last_paste: Option<(Rect, f64)>,
+    /// Levels above the layers being edited, outermost first, while inside a group.
+    outside: Vec<Outside>,
+    /// Whether layers were added, removed, reordered, changed or entered since the last
+    /// `take_layers_changed`.
+    layers_changed: bool,
+    /// Called when there is something new to draw or report.
+    on_change: Option<Box<dyn Fn()>>,
 }
 
 impl Layers {
     pub fn new() -> Self {
-        Layers { layers: Vec::new(), active_layer: None, outlined_layer: None }
+        Layers {
+            layers: LayerStack::new(),
+            selection: Vec::new(),
+            state: None,
+            outlined_layer: None,
+            tool: Tool::Select,
+            history: History::new(),
+            snapshots: Vec::new(),
+            last_id: 0,
+            viewport: Viewport::default(),
+            pan_key_held: false,
+            damage: Damage::Everything,
+            index: SpatialIndex::new(),
+            clipboard: Vec::new(),
+            last_paste: None,
+            outside: Vec::new(),
+            layers_changed: true,
+            on_change: None,
+        }
     }
 
-    pub fn layers(&self) -> &Vec<Layer> {
+    /// The layers being edited, which are the children of a group while inside it.
+    pub fn layers(&self) -> &LayerStack {
         &self.layers
     }
 
-    pub fn outlined_layer(&self) -> &Option<usize> {
+    /// Top-level layers of the document, also while editing inside a group.
+    pub fn document(&self) -> &LayerStack {
+        self.outside
+            .first()
+            .map_or(&self.layers, |outside| &outside.layers)
+    }
+
+    /// Every layer as it is drawn, bottom to top. Inside a group, the layers being edited
+    /// stand in for it.
+    pub fn drawn_layers(&self) -> Vec<&Layer> {
+        let mut drawn = Vec::new();
+        self.collect_drawn(0, &mut drawn);
+        drawn
+    }
+
+    fn collect_drawn<'a>(&'a self, level: usize, drawn: &mut Vec<&'a Layer>) {
+        let Some(outside) = self.outside.get(level) else {
+            return drawn.extend(self.layers.iter());
+        };
+        for layer in &outside.layers {
+            if layer.id == outside.group {
+                self.collect_drawn(level + 1, drawn);
+            } else {
+                drawn.push(layer);
+            }
+        }
+    }
+
+    /// Box around the children of the entered group, if any.
+    pub fn entered_bounds(&self) -> Option<Rect> {
+        if self.outside.is_empty() {
+            return None;
+        }
+        self.layers
+            .iter()
+            .map(|layer| layer.object.world_bounds())
+            .reduce(|a, b| a.union(&b))
+    }
+
+    pub fn outlined_layer(&self) -> &Option<LayerId> {
         &self.outlined_layer
     }
 
-    pub fn active_layer(&self) -> &Option<LayerState> {
-        &self.active_layer
+    /// The gesture in progress, if any.
+    pub fn state(&self) -> &Option<LayerState> {
+        &self.state
     }
 
-    pub fn on_event(&mut self, event: Event, canvas: &Canvas) {
-        match event {
-            Event::MouseDown { point } => self.on_mouse_down(point, canvas),
-            Event::MouseMove { point } => self.on_mouse_move(point, canvas),
-            Event::MouseUp { point } => self.on_mouse_up(point, canvas),
+    /// Selected layers, in the order they were selected.
+    pub fn selection(&self) -> &[LayerId] {
+        &self.selection
+    }
+
+    /// Selects the layers with `ids`, skipping ones that are not in the stack or are hidden or
+    /// locked.
+    pub fn set_selection(&mut self, ids: &[LayerId]) {
+        if self.is_in_gesture() {
+            return;
+        }
+        self.selection.clear();
+        for &id in ids {
+            let selectable = self.layers.get(id).is_some_and(Layer::selectable);
+            if selectable && !self.selection.contains(&id) {
+                self.selection.push(id);
+            }
+        }
+        self.invalidate();
+    }
+
+    /// Box around every selected layer.
+    pub fn selection_bounds(&self) -> Option<Rect> {
+        self.selection
+            .iter()
+            .map(|&layer| self.layers.layer(layer).object.world_bounds())
+            .reduce(|a, b| a.union(&b))
+    }
+
+    /// Resize handles of the selection: the object's own for a single layer, in its unrotated
+    /// frame, otherwise the corners of the box around all of them.
+    pub fn selection_edges(&self) -> Vec<Edge> {
+        match self.selection[..] {
+            [] => Vec::new(),
+            [layer] => self.layers.layer(layer).object.edges(),
+            _ => self.selection_bounds().unwrap().edges(),
+        }
+    }
+
+    /// What needs to be drawn again since the last call. Drawing is left to the render loop so
+    /// that several events within one frame cost a single redraw.
+    pub fn take_damage(&mut self) -> Damage {
+        std::mem::take(&mut self.damage)
+    }
+
+    /// Has `on_change` called whenever damage or a change of the layers is marked, which the
+    /// render loop waits for.
+    pub fn set_on_change(&mut self, on_change: impl Fn() + 'static) {
+        self.on_change = Some(Box::new(on_change));
+    }
+
+    fn changed(&self) {
+        if let Some(on_change) = &self.on_change {
+            on_change();
+        }
+    }
+
+    fn invalidate(&mut self) {
+        self.damage = Damage::Everything;
+        self.changed();
+    }
+
+    fn mark_layers_changed(&mut self) {
+        self.layers_changed = true;
+        self.changed();
+    }
+
+    /// Whether the layers changed since the last call, for telling a layer panel to update.
+    /// Like drawing, that is left to the render loop, once per frame.
+    pub fn take_layers_changed(&mut self) -> bool {
+        std::mem::take(&mut self.layers_changed)
+    }
+
+    /// Marks where `layer` is drawn, along with its outline and handles. Called both before
+    /// and after changing a layer, so that the old and the new look are repainted. Inside a
+    /// group, the box drawn around its children may grow or shrink with the layer.
+    fn invalidate_layer(&mut self, layer: LayerId) {
+        self.invalidate_area(&self.layers.layer(layer).object.paint_bounds());
+        if let Some(bounds) = self.entered_bounds() {
+            self.invalidate_area(&bounds);
+        }
+    }
+
+    fn invalidate_selection(&mut self) {
+        for idx in 0..self.selection.len() {
+            self.invalidate_layer(self.selection[idx]);
+        }
+    }
+
+    /// Files every layer in the spatial index anew.
+    fn reindex(&mut self) {
+        let bounds = self
+            .layers
+            .iter()
+            .map(|layer| (layer.id, layer.object.paint_bounds()));
+        self.index.rebuild(bounds);
+    }
+
+    /// Updates the spatial index after `layer` was added or changed its shape or place.
+    fn reindex_layer(&mut self, layer: LayerId) {
+        self.index
+            .insert(layer, &self.layers.layer(layer).object.paint_bounds());
+    }
+
+    fn reindex_selection(&mut self) {
+        for idx in 0..self.selection.len() {
+            self.reindex_layer(self.selection[idx]);
+        }
+    }
+
+    /// Marks `area` of the document, along with anything drawn on top of it close by.
+    fn invalidate_area(&mut self, area: &Rect) {
+        let area = Rect::new(
+            self.viewport.to_screen(area.top_left()),
+            self.viewport.to_screen(area.bottom_right()),
+        );
+        self.damage.add(&area.inflate(OVERLAY_MARGIN));
+        self.changed();
+    }
+
+    pub fn viewport(&self) -> &Viewport {
+        &self.viewport
+    }
+
+    /// Zooms around the center of the canvas.
+    pub fn set_zoom(&mut self, zoom: f64, surface: &dyn Surface) {
+        let center = Point { x: surface.width() / 2.0, y: surface.height() / 2.0 };
+        self.viewport.zoom_at(zoom, center);
+        self.invalidate();
+    }
+
+    /// Keeps the middle of the view in place after the canvas changed from `old_width` by
+    /// `old_height` to the size of `surface`. Resizing a canvas erases it, so this asks for a
+    /// redraw.
+    pub fn on_resize(&mut self, old_width: f64, old_height: f64, surface: &dyn Surface) {
+        let offset = Point {
+            x: (surface.width() - old_width) / 2.0,
+            y: (surface.height() - old_height) / 2.0,
+        };
+        self.viewport.pan(offset);
+        self.invalidate();
+    }
+
+    /// Zooms and scrolls so that every layer is in view. An empty document resets the view.
+    pub fn zoom_to_fit(&mut self, surface: &dyn Surface) {
+        let bounds = self
+            .document()
+            .iter()
+            .map(|layer| layer.object.world_bounds())
+            .reduce(|a, b| a.union(&b));
+        match bounds {
+            Some(bounds) => self
+                .viewport
+                .fit(&bounds, surface.width(), surface.height()),
+            None => self.viewport = Viewport::default(),
+        }
+        self.invalidate();
+    }
+
+    /// Replaces the whole layer stack, e.g. with a loaded document. History is cleared. Layers
+    /// whose id is already taken by one below them get a new one.
+    pub fn load(&mut self, layers: Vec<Layer>) {
+        self.outside.clear();
+        self.last_id = layers.iter().map(|layer| layer.id).max().unwrap_or(0);
+        self.layers = LayerStack::new();
+        for mut layer in layers {
+            self.claim_id(&mut layer);
+            self.layers.push(layer);
+        }
+        self.selection.clear();
+        self.state = None;
+        self.outlined_layer = None;
+        self.history = History::new();
+        self.snapshots.clear();
+        self.reindex();
+        self.mark_layers_changed();
+        self.invalidate();
+    }
+
+    pub fn tool(&self) -> Tool {
+        self.tool
+    }
+
+    pub fn set_tool(&mut self, tool: Tool) {
+        self.tool = tool;
+    }
+
+    /// Style of the first selected layer, if any.
+    pub fn selected_style(&self) -> Option<&Style> {
+        self.selection
+            .first()
+            .map(|&layer| self.layers.layer(layer).object.style())
+    }
+
+    /// Content of the first selected text, if any.
+    pub fn selected_text(&self) -> Option<&str> {
+        self.selection
+            .iter()
+            .find_map(|&layer| self.layers.layer(layer).object.as_text())
+            .map(Text::content)
+    }
+
+    /// Replaces the content of every selected text as one undoable step.
+    pub fn set_text(&mut self, content: &str) {
+        if self.is_in_gesture() {
+            return;
+        }
+        let mut commands = Vec::new();
+        for &id in &self.selection {
+            let object = &mut self.layers.layer_mut(id).object;
+            let before = object.clone();
+            match object.as_text_mut() {
+                Some(text) if text.content() != content => text.set_content(content),
+                _ => continue,
+            }
+            let after = object.clone();
+            commands.push(Command::Modify { id, before, after });
+        }
+        if !commands.is_empty() {
+            self.push_commands(commands);
+            self.invalidate_selection();
+        }
+    }
+
+    /// Changes the style of every selected layer as one undoable step.
+    pub fn update_style(&mut self, update: impl Fn(&mut Style)) {
+        if self.is_in_gesture() {
+            return;
+        }
+        let mut commands = Vec::new();
+        for &id in &self.selection {
+            let object = &mut self.layers.layer_mut(id).object;
+            let before = object.clone();
+            if object.update_style(&update) {
+                let after = object.clone();
+                commands.push(Command::Modify { id, before, after });
+            }
+        }
+        if !commands.is_empty() {
+            self.push_commands(commands);
+            self.reindex_selection();
+            self.invalidate();
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.history.can_undo()
+            || self
+                .outside
+                .iter()
+                .any(|outside| outside.history.can_undo())
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.history.can_redo()
+    }
+
+    pub fn undo(&mut self) {
+        // Once nothing is left to undo inside a group, undo goes on outside of it
+        while !self.history.can_undo() && !self.outside.is_empty() && !self.is_in_gesture() {
+            self.exit_group();
+        }
+        if self.can_undo() && !self.is_in_gesture() {
+            let selection = self.history.undo(&mut self.layers);
+            self.after_history_change(selection);
+        }
+    }
+
+    pub fn redo(&mut self) {
+        if self.can_redo() && !self.is_in_gesture() {
+            let selection = self.history.redo(&mut self.layers);
+            self.after_history_change(selection);
+        }
+    }
+
+    fn is_in_gesture(&self) -> bool {
+        self.state.is_some()
+    }
+
+    fn after_history_change(&mut self, mut selection: Vec<LayerId>) {
+        selection.retain(|&id| self.layers.layer(id).selectable());
+        self.selection = selection;
+        self.outlined_layer = None;
+        self.reindex();
+        self.sync_groups();
+        self.mark_layers_changed();
+        self.invalidate();
+    }
+
+    /// Records `commands` as a single history entry. A group whose last child they removed
+    /// is left right away, which removes it from the document.
+    fn push_commands(&mut self, mut commands: Vec<Command>) {
+        match commands.len() {
+            0 => return,
+            1 => self.history.push(commands.pop().unwrap()),
+            _ => self.history.push(Command::Batch(commands)),
+        }
+        if self.layers.is_empty() && !self.outside.is_empty() {
+            return self.exit_group();
+        }
+        self.sync_groups();
+        self.mark_layers_changed();
+    }
+
+    /// Changes the name, visibility or lock of the layer `id` as one undoable step. A layer
+    /// that gets hidden or locked is deselected.
+    pub fn set_layer_meta(&mut self, id: LayerId, update: impl FnOnce(&mut LayerMeta)) {
+        if self.is_in_gesture() || self.layers.get(id).is_none() {
+            return;
+        }
+        let layer = self.layers.layer_mut(id);
+        let before = layer.meta.clone();
+        update(&mut layer.meta);
+        if layer.meta == before {
+            return;
+        }
+        let after = layer.meta.clone();
+        if !layer.selectable() {
+            self.selection.retain(|&selected| selected != id);
+            if self.outlined_layer == Some(id) {
+                self.outlined_layer = None;
+            }
+        }
+        self.push_commands(vec![Command::Meta { id, before, after }]);
+        self.invalidate();
+    }
+
+    /// Puts the selected layers into a new group, where the topmost of them was, as one
+    /// undoable step.
+    pub fn group_selection(&mut self) {
+        if self.is_in_gesture() || self.selection.is_empty() {
+            return;
+        }
+        let selected = self.stacked(self.selection.clone());
+        let top = self.layers.position(*selected.last().unwrap()).unwrap();
+        let position = top + 1 - selected.len();
+        let children = selected
+            .iter()
+            .map(|&id| self.layers.layer(id).clone())
+            .collect();
+        let mut commands = self.remove_layers(selected);
+        let id = self.next_id();
+        let object: Box<dyn Object> = Box::new(Group::new(children));
+        let meta = LayerMeta::default();
+        commands.push(Command::Create { position, id, object: object.clone(), meta });
+        self.layers.insert(position, Layer::new(id, object));
+        self.reindex_layer(id);
+        self.push_commands(commands);
+        self.selection = vec![id];
+        self.outlined_layer = None;
+        self.invalidate();
+    }
+
+    /// Replaces every selected group with its children, which become selected, as one
+    /// undoable step.
+    pub fn ungroup_selection(&mut self) {
+        if self.is_in_gesture() {
+            return;
+        }
+        let (groups, mut selection): (Vec<_>, Vec<_>) = std::mem::take(&mut self.selection)
+            .into_iter()
+            .partition(|&id| self.layers.layer(id).object.as_group().is_some());
+        let mut commands = Vec::new();
+        for id in groups {
+            let (position, Layer { id, object, meta }) = self.layers.remove(id);
+            self.index.remove(id);
+            let children = object.as_group().unwrap().children().to_vec();
+            commands.push(Command::Delete { position, id, object, meta });
+            for (position, mut layer) in (position..).zip(children) {
+                self.claim_id(&mut layer);
+                let Layer { id, object, meta } = layer.clone();
+                commands.push(Command::Create { position, id, object, meta });
+                if layer.selectable() {
+                    selection.push(id);
+                }
+                self.layers.insert(position, layer);
+                self.reindex_layer(id);
+            }
+        }
+        self.push_commands(commands);
+        self.selection = selection;
+        self.outlined_layer = None;
+        self.invalidate();
+    }
+
+    /// Edits the children of the group `id` as layers of their own until `exit_group`.
+    fn enter_group(&mut self, id: LayerId) {
+        let before = self.layers.layer(id).object.clone();
+        let children = before.as_group().unwrap().children().to_vec();
+        let outside = Outside {
+            layers: std::mem::replace(&mut self.layers, LayerStack::new()),
+            history: std::mem::replace(&mut self.history, History::new()),
+            index: std::mem::replace(&mut self.index, SpatialIndex::new()),
+            group: id,
+            before,
+        };
+        self.outside.push(outside);
+        for mut child in children {
+            self.claim_id(&mut child);
+            self.layers.push(child);
+        }
+        self.selection.clear();
+        self.outlined_layer = None;
+        self.reindex();
+        self.mark_layers_changed();
+        self.invalidate();
+    }
+
+    /// Goes back to the level the current group was entered from and selects the group. The
+    /// edits made inside become one undoable change of the group there, or its removal when
+    /// no children are left.
+    fn exit_group(&mut self) {
+        let Some(outside) = self.outside.pop() else {
+            return;
+        };
+        let children = std::mem::replace(&mut self.layers, outside.layers);
+        // Whatever is left to undo inside changed the group
+        let changed = self.history.can_undo();
+        self.history = outside.history;
+        self.index = outside.index;
+        let id = outside.group;
+        self.selection.clear();
+        if children.is_empty() {
+            let (position, Layer { meta, .. }) = self.layers.remove(id);
+            self.index.remove(id);
+            let object = outside.before;
+            self.push_commands(vec![Command::Delete { position, id, object, meta }]);
+        } else {
+            if changed {
+                let after = self.layers.layer(id).object.clone();
+                let before = outside.before;
+                self.push_commands(vec![Command::Modify { id, before, after }]);
+            }
+            self.reindex_layer(id);
+            self.selection.push(id);
+        }
+        self.outlined_layer = None;
+        self.mark_layers_changed();
+        self.invalidate();
+    }
+
+    /// Rebuilds the entered groups from the layers edited inside them, so that the document
+    /// is whole after every change.
+    fn sync_groups(&mut self) {
+        for level in (0..self.outside.len()).rev() {
+            let children = match self.outside.get(level + 1) {
+                Some(inner) => &inner.layers,
+                None => &self.layers,
+            };
+            let group = Box::new(Group::new(children.iter().cloned().collect()));
+            let outside = &mut self.outside[level];
+            outside.layers.layer_mut(outside.group).object = group;
+        }
+    }
+
+    pub fn on_event(&mut self, event: Event, surface: &dyn Surface) {
+        match &event {
+            Event::MouseDown { point, modifiers } => {
+                self.on_mouse_down(self.viewport.to_world(*point), *modifiers)
+            }
+            Event::MouseMove { point, modifiers } => {
+                self.on_mouse_move(self.viewport.to_world(*point), *modifiers)
+            }
+            Event::MouseUp { .. } => self.on_mouse_up(),
+            Event::DoubleClick { point, .. } => {
+                self.on_double_click(self.viewport.to_world(*point))
+            }
+            Event::Wheel { point, delta, .. } => self.on_wheel(*point, *delta),
+            Event::KeyDown { key, modifiers } => self.on_key_down(key, *modifiers, surface),
+            Event::KeyUp { key, modifiers } => self.on_key_up(key, *modifiers, surface),
         }
 
         match event {
-            Event::MouseDown { point } | Event::MouseMove { point } | Event::MouseUp { point } => {
-                self.set_cursor(point, canvas);
+            Event::MouseDown { point, .. }
+            | Event::MouseMove { point, .. }
+            | Event::MouseUp { point, .. }
+            | Event::DoubleClick { point, .. }
+            | Event::Wheel { point, .. } => {
+                self.set_cursor(self.viewport.to_world(point), surface);
             }
+            Event::KeyDown { .. } | Event::KeyUp { .. } => {}
         }
     }
 
-    fn on_mouse_down(&mut self, point: Point, canvas: &Canvas) {
-        match self.active_layer {
-            None => {
-                let maybe_active_layer = self.find_layer_from_point(point, canvas);
-                if let Some(layer) = maybe_active_layer {
-                    self.active_layer = Some(LayerState::Idle { layer });
-                } else {
-                    self.active_layer = Some(LayerState::ToCreate { start: point });
+    fn on_wheel(&mut self, point: Point, delta: f64) {
+        let zoom = self.viewport.zoom() * WHEEL_ZOOM_STEP.powf(-delta);
+        self.viewport.zoom_at(zoom, point);
+        self.invalidate();
+    }
+
+    fn on_key_down(&mut self, key: &str, modifiers: Modifiers, surface: &dyn Surface) {
+        match Shortcut::from_key(key, modifiers) {
+            Some(Shortcut::Undo) => self.undo(),
+            Some(Shortcut::Redo) => self.redo(),
+            Some(Shortcut::Delete) => self.delete_selection(),
+            Some(Shortcut::Duplicate) => self.duplicate_selection(),
+            Some(Shortcut::Nudge(offset)) => self.nudge_selection(offset),
+            Some(Shortcut::Cancel) => self.cancel(),
+            Some(Shortcut::SelectAll) => self.select_all(),
+            Some(Shortcut::Group) => self.group_selection(),
+            Some(Shortcut::Ungroup) => self.ungroup_selection(),
+            Some(Shortcut::Reorder(reorder)) => self.reorder_selection(reorder),
+            Some(Shortcut::FlipHorizontal) => self.transform_selection(Transform::scale(-1.0, 1.0)),
+            Some(Shortcut::FlipVertical) => self.transform_selection(Transform::scale(1.0, -1.0)),
+            Some(Shortcut::Pan) => {
+                self.pan_key_held = true;
+                if !self.is_in_gesture() {
+                    surface.set_cursor("grab");
                 }
-                canvas.render(self);
-            }
-            Some(LayerState::Idle { layer }) => {
-                let active_layer = &self.layers[layer];
-                if let Some(edge) = active_layer.point_over_edge(canvas, point) {
-                    self.active_layer = Some(LayerState::Resize { layer, edge });
-                } else if let Some(layer) = self.find_layer_from_point(point, canvas) {
-                    let grab_point = self.layers[layer].object.grab_point(point);
-                    self.active_layer = Some(LayerState::Relocate { layer, grab_point });
-                } else {
-                    self.active_layer = None;
+            }
+            None => {}
+        }
+    }
+
+    fn on_key_up(&mut self, key: &str, modifiers: Modifiers, surface: &dyn Surface) {
+        if Shortcut::from_key(key, modifiers) == Some(Shortcut::Pan) {
+            self.pan_key_held = false;
+            if !self.is_in_gesture() {
+                surface.set_cursor(self.tool.cursor());
+            }
+        }
+    }
+
+    /// Removes the selected layers as one undoable step.
+    pub fn delete_selection(&mut self) {
+        if self.is_in_gesture() || self.selection.is_empty() {
+            return;
+        }
+        let selection = std::mem::take(&mut self.selection);
+        let commands = self.remove_layers(selection);
+        self.push_commands(commands);
+        self.outlined_layer = None;
+        self.invalidate();
+    }
+
+    /// Takes the layers `ids` out of the stack, returning the commands that put them back.
+    fn remove_layers(&mut self, mut ids: Vec<LayerId>) -> Vec<Command> {
+        // Remove from the top, so that undoing puts them back bottom to top where they were
+        ids.sort_unstable_by_key(|&id| Reverse(self.layers.position(id)));
+        ids.into_iter()
+            .map(|id| {
+                let (position, Layer { id, object, meta }) = self.layers.remove(id);
+                self.index.remove(id);
+                Command::Delete { position, id, object, meta }
+            })
+            .collect()
+    }
+
+    /// Puts copies of the selected objects on the clipboard, replacing what was there.
+    pub fn copy_selection(&mut self) {
+        if self.selection.is_empty() {
+            return;
+        }
+        self.clipboard = self
+            .stacked(self.selection.clone())
+            .into_iter()
+            .map(|layer| self.layers.layer(layer).object.clone())
+            .collect();
+    }
+
+    pub fn clipboard(&self) -> &[Box<dyn Object>] {
+        &self.clipboard
+    }
+
+    pub fn paste_clipboard(&mut self) {
+        self.paste(self.clipboard.clone());
+    }
+
+    pub fn duplicate_selection(&mut self) {
+        let objects = self
+            .stacked(self.selection.clone())
+            .into_iter()
+            .map(|layer| self.layers.layer(layer).object.clone())
+            .collect();
+        self.paste(objects);
+    }
+
+    /// Adds `objects`, bottom to top, above all layers and `PASTE_OFFSET` away from where they
+    /// were, or from the previous paste of the same objects, then selects them. The whole
+    /// paste is one undoable step.
+    pub fn paste(&mut self, objects: Vec<Box<dyn Object>>) {
+        if self.is_in_gesture() || objects.is_empty() {
+            return;
+        }
+        let bounds = objects
+            .iter()
+            .map(|object| object.world_bounds())
+            .reduce(|a, b| a.union(&b))
+            .unwrap();
+        let offset = match &self.last_paste {
+            Some((pasted, offset))
+                if pasted.top_left() == bounds.top_left()
+                    && pasted.bottom_right() == bounds.bottom_right() =>
+            {
+                offset + PASTE_OFFSET
+            }
+            _ => PASTE_OFFSET,
+        };
+        self.last_paste = Some((bounds, offset));
+        self.selection.clear();
+        let mut commands = Vec::new();
+        for mut object in objects {
+            object.translate(Point { x: offset, y: offset });
+            let (position, id) = (self.layers.len(), self.next_id());
+            let meta = LayerMeta::default();
+            commands.push(Command::Create { position, id, object: object.clone(), meta });
+            self.layers.push(Layer::new(id, object));
+            self.reindex_layer(id);
+            self.selection.push(id);
+        }
+        self.push_commands(commands);
+        self.invalidate();
+    }
+
+    /// Moves the selected layers by `offset` as one undoable step.
+    pub fn nudge_selection(&mut self, offset: Point) {
+        if self.is_in_gesture() || self.selection.is_empty() {
+            return;
+        }
+        let mut commands = Vec::new();
+        for &id in &self.selection {
+            let object = &mut self.layers.layer_mut(id).object;
+            let before = object.clone();
+            object.translate(offset);
+            let after = object.clone();
+            commands.push(Command::Modify { id, before, after });
+        }
+        self.push_commands(commands);
+        self.reindex_selection();
+        self.invalidate();
+    }
+
+    /// Applies `transform`, with the center of the selection as its origin, to every selected
+    /// layer as one undoable step.
+    pub fn transform_selection(&mut self, transform: Transform) {
+        if self.is_in_gesture() {
+            return;
+        }
+        let Some(bounds) = self.selection_bounds() else {
+            return;
+        };
+        let transform = transform.around(bounds.center());
+        let mut commands = Vec::new();
+        for &id in &self.selection {
+            let object = &mut self.layers.layer_mut(id).object;
+            let before = object.clone();
+            object.apply_transform(transform);
+            let after = object.clone();
+            commands.push(Command::Modify { id, before, after });
+        }
+        self.push_commands(commands);
+        self.reindex_selection();
+        self.invalidate();
+    }
+
+    /// Aborts the gesture in progress, putting every layer back as it was before it started.
+    /// Without a gesture the selection is cleared instead.
+    pub fn cancel(&mut self) {
+        match self.state.take() {
+            Some(LayerState::Creating { layer, .. }) => {
+                self.layers.remove(layer);
+                self.index.remove(layer);
+                self.selection.clear();
+            }
+            Some(LayerState::Resize { .. })
+            | Some(LayerState::Relocate { .. })
+            | Some(LayerState::Rotate { .. }) => {
+                for (layer, snapshot) in std::mem::take(&mut self.snapshots) {
+                    self.layers.layer_mut(layer).object = snapshot;
+                    self.reindex_layer(layer);
                 }
-                canvas.render(self);
             }
-            _ => {}
+            Some(LayerState::ToCreate { .. })
+            | Some(LayerState::Marquee { .. })
+            | Some(LayerState::Pan { .. }) => {}
+            None if !self.outside.is_empty() => self.exit_group(),
+            None => self.selection.clear(),
         }
+        self.invalidate();
     }
 
-    fn on_mouse_move(&mut self, point: Point, canvas: &Canvas) {
-        match self.active_layer {
-            Some(LayerState::ToCreate { start }) => {
+    /// Moves the selected layers up or down the stack as one undoable step. Selected layers
+    /// keep their order among themselves.
+    pub fn reorder_selection(&mut self, reorder: Reorder) {
+        if self.is_in_gesture() || self.selection.is_empty() {
+            return;
+        }
+        let mut selected = self.stacked(self.selection.clone());
+        // Go from the side the layers move towards, so that each one stops below or above the
+        // ones already moved
+        if matches!(reorder, Reorder::Forward | Reorder::ToFront) {
+            selected.reverse();
+        }
+        let top = self.layers.len() - 1;
+        let mut commands = Vec::new();
+        for (moved, &id) in selected.iter().enumerate() {
+            let from = self.layers.position(id).unwrap();
+            let to = match reorder {
+                Reorder::Forward => (from + 1).min(top - moved),
+                Reorder::Backward => from.saturating_sub(1).max(moved),
+                Reorder::ToFront => top - moved,
+                Reorder::ToBack => moved,
+            };
+            if to != from {
+                self.layers.move_to(id, to);
+                commands.push(Command::Reorder { id, from, to });
+            }
+        }
+        if !commands.is_empty() {
+            self.push_commands(commands);
+            self.invalidate();
+        }
+    }
+
+    pub fn select_all(&mut self) {
+        if self.is_in_gesture() {
+            return;
+        }
+        self.selection = self
+            .layers
+            .iter()
+            .filter(|layer| layer.selectable())
+            .map(|layer| layer.id)
+            .collect();
+        self.invalidate();
+    }
+
+    fn on_mouse_down(&mut self, point: Point, modifiers: Modifiers) {
+        if self.is_in_gesture() {
+            return;
+        }
+        if self.tool == Tool::Pan || self.pan_key_held {
+            self.state = Some(LayerState::Pan { origin: point });
+            return;
+        }
+        if self.point_over_rotate_handle(point) {
+            let center = self.layers.layer(self.selection[0]).object.center();
+            let start_angle = point.subtract(center).angle();
+            self.state = Some(LayerState::Rotate { center, start_angle });
+            self.invalidate();
+            return;
+        }
+        if let Some(edge) = self.point_over_selection_edge(point) {
+            self.state = Some(LayerState::Resize { edge, grabbed: edge });
+            self.invalidate();
+            return;
+        }
+
+        // Clicking outside of the group being edited leaves it
+        if self.tool == Tool::Select {
+            while !self.outside.is_empty() && self.find_layer_from_point(point).is_none() {
+                self.exit_group();
+            }
+        }
+
+        match self.tool {
+            Tool::Select => match self.find_layer_from_point(point) {
+                Some(layer) if modifiers.shift => self.toggle_selected(layer),
+                Some(layer) => {
+                    if !self.selection.contains(&layer) {
+                        self.selection = vec![layer];
+                    }
+                    self.state = Some(LayerState::Relocate { origin: point });
+                }
+                None => {
+                    if !modifiers.shift {
+                        self.selection.clear();
+                    }
+                    self.state = Some(LayerState::Marquee {
+                        start: point,
+                        end: point,
+                        additive: modifiers.shift,
+                    });
+                }
+            },
+            Tool::Create(kind) => {
+                self.selection.clear();
+                self.state = Some(LayerState::ToCreate { kind, start: point });
+            }
+            Tool::Pan => unreachable!(),
+        }
+        self.invalidate();
+    }
+
+    /// Double-clicking a group enters it, with the child under the pointer selected.
+    fn on_double_click(&mut self, point: Point) {
+        if self.is_in_gesture() || self.tool != Tool::Select {
+            return;
+        }
+        let Some(layer) = self.find_layer_from_point(point) else {
+            return;
+        };
+        if self.layers.layer(layer).object.as_group().is_some() {
+            self.enter_group(layer);
+            self.selection.extend(self.find_layer_from_point(point));
+        }
+    }
+
+    fn on_mouse_move(&mut self, point: Point, modifiers: Modifiers) {
+        match self.state {
+            Some(LayerState::ToCreate { kind, start }) => {
+                let layer = self.next_id();
                 self.layers
-                    .push(Layer { object: Box::new(Rect::new(start, point)) });
-                let layer = self.layers.len() - 1;
-                self.active_layer = Some(LayerState::Creating { layer, start });
-                canvas.render(self);
+                    .push(Layer::new(layer, kind.create(start, point)));
+                self.selection = vec![layer];
+                self.state = Some(LayerState::Creating { kind, layer, start });
+                self.reindex_layer(layer);
+                self.invalidate_layer(layer);
             }
-            Some(LayerState::Creating { layer, start }) => {
-                self.layers[layer] = Layer { object: Box::new(Rect::new(start, point)) };
-                canvas.render(self);
+            Some(LayerState::Creating { kind, layer, start }) => {
+                self.invalidate_layer(layer);
+                self.layers.layer_mut(layer).object = kind.create(start, point);
+                self.reindex_layer(layer);
+                self.invalidate_layer(layer);
             }
-            Some(LayerState::Resize { layer, edge }) => {
-                self.layers[layer].object.resize(point, edge);
-                canvas.render(self);
+            Some(LayerState::Resize { edge, grabbed }) => {
+                self.invalidate_selection();
+                self.take_snapshots();
+                let constrained = modifiers.shift || modifiers.alt;
+                let edge = match self.selection[..] {
+                    [layer] if !constrained => self
+                        .layers
+                        .layer_mut(layer)
+                        .object
+                        .resize_in_world(point, edge),
+                    _ => self.resize_by_bounds(point, grabbed, modifiers),
+                };
+                self.state = Some(LayerState::Resize { edge, grabbed });
+                self.reindex_selection();
+                self.invalidate_selection();
             }
-            Some(LayerState::Relocate { layer, grab_point }) => {
-                self.layers[layer].object.relocate(point, grab_point);
-                canvas.render(self);
+            Some(LayerState::Relocate { origin }) => {
+                self.invalidate_selection();
+                self.take_snapshots();
+                for (layer, snapshot) in &self.snapshots {
+                    let mut object = snapshot.clone();
+                    object.translate(point.subtract(origin));
+                    self.layers.layer_mut(*layer).object = object;
+                }
+                self.reindex_selection();
+                self.invalidate_selection();
+            }
+            Some(LayerState::Rotate { center, start_angle }) => {
+                self.invalidate_selection();
+                self.take_snapshots();
+                let (layer, snapshot) = &self.snapshots[0];
+                let mut angle = point.subtract(center).angle() - start_angle;
+                if modifiers.shift {
+                    let rotation = snapshot.rotation() + angle;
+                    angle =
+                        (rotation / ROTATION_SNAP).round() * ROTATION_SNAP - snapshot.rotation();
+                }
+                let mut object = snapshot.clone();
+                object.rotate(angle);
+                self.layers.layer_mut(*layer).object = object;
+                self.reindex_selection();
+                self.invalidate_selection();
+            }
+            Some(LayerState::Marquee { start, end, additive }) => {
+                self.invalidate_area(&Rect::new(start, end));
+                self.state = Some(LayerState::Marquee { start, end: point, additive });
+                self.invalidate_area(&Rect::new(start, point));
             }
-            _ => {
+            Some(LayerState::Pan { origin }) => {
+                let offset = point.subtract(origin).scale(self.viewport.zoom());
+                self.viewport.pan(offset);
+                self.invalidate();
+            }
+            None => {
                 // Outlined layer
-                let maybe_outlined_layer = self.find_layer_from_point(point, canvas);
-                if let Some(outlined_layer) = maybe_outlined_layer {
-                    self.outlined_layer = Some(outlined_layer);
-                    canvas.render(self);
-                } else if self.outlined_layer.is_some() {
-                    self.outlined_layer = None;
-                    canvas.render(self);
+                let outlined_layer = self.find_layer_from_point(point);
+                if outlined_layer != self.outlined_layer {
+                    for layer in [self.outlined_layer, outlined_layer].into_iter().flatten() {
+                        self.invalidate_layer(layer);
+                    }
+                    self.outlined_layer = outlined_layer;
                 }
             }
         }
     }
 
-    fn on_mouse_up(&mut self, _point: Point, _canvas: &Canvas) {
-        match self.active_layer {
-            Some(LayerState::ToCreate { .. }) => {
-                self.active_layer = None;
+    fn on_mouse_up(&mut self) {
+        match self.state.take() {
+            Some(LayerState::Creating { layer, .. }) => {
+                let position = self.layers.position(layer).unwrap();
+                let Layer { id, object, meta } = self.layers.layer(layer).clone();
+                self.push_commands(vec![Command::Create { position, id, object, meta }]);
+            }
+            Some(LayerState::Resize { .. })
+            | Some(LayerState::Relocate { .. })
+            | Some(LayerState::Rotate { .. }) => {
+                let commands = self
+                    .snapshots
+                    .drain(..)
+                    .map(|(id, before)| {
+                        let after = self.layers.layer(id).object.clone();
+                        Command::Modify { id, before, after }
+                    })
+                    .collect();
+                self.push_commands(commands);
             }
-            Some(LayerState::Creating { layer, .. })
-            | Some(LayerState::Resize { layer, .. })
-            | Some(LayerState::Relocate { layer, .. }) => {
-                self.active_layer = Some(LayerState::Idle { layer });
+            Some(LayerState::Marquee { start, end, additive }) => {
+                let layers = self.find_layers_in_area(&Rect::new(start, end));
+                if !additive {
+                    self.selection.clear();
+                }
+                for layer in layers {
+                    if !self.selection.contains(&layer) {
+                        self.selection.push(layer);
+                    }
+                }
+                self.invalidate();
             }
-            _ => {}
+            Some(LayerState::ToCreate { .. }) | Some(LayerState::Pan { .. }) | None => {}
         }
     }
 
-    fn set_cursor(&self, point: Point, canvas: &Canvas) {
-        match self.active_layer {
-            Some(LayerState::Idle { layer }) => {
-                let maybe_edge = self.layers[layer].point_over_edge(canvas, point);
-                if let Some(edge) = maybe_edge {
-                    edge.set_cursor(canvas.canvas())
-                } else {
-                    set_default_cursor(canvas)
-                }
+    fn toggle_selected(&mut self, layer: LayerId) {
+        if let Some(idx) = self
+            .selection
+            .iter()
+            .position(|&selected| selected == layer)
+        {
+            self.selection.remove(idx);
+        } else {
+            self.selection.push(layer);
+        }
+    }
+
+    /// Scales every selected layer along with the box around them. Shift keeps the aspect
+    /// ratio of the box and Alt resizes it from its center. A single layer is resized in its
+    /// own local frame. Returns the dragged handle, flipped if the box was dragged inside out.
+    fn resize_by_bounds(&mut self, point: Point, edge: Edge, modifiers: Modifiers) -> Edge {
+        if let [(layer, snapshot)] = &self.snapshots[..] {
+            let from = snapshot.bounds();
+            let to = from.resized(
+                snapshot.to_local(point),
+                edge,
+                modifiers.shift,
+                modifiers.alt,
+            );
+            let mut object = snapshot.clone();
+            let map = from.map_onto(&to);
+            object.map_points(&|point| map.apply(point));
+            self.layers.layer_mut(*layer).object = object;
+            return edge.on_resized(&to);
+        }
+
+        let Some(from) = self
+            .snapshots
+            .iter()
+            .map(|(_, snapshot)| snapshot.world_bounds())
+            .reduce(|a, b| a.union(&b))
+        else {
+            return edge;
+        };
+        let to = from.resized(point, edge, modifiers.shift, modifiers.alt);
+        let map = from.map_onto(&to);
+        for (layer, snapshot) in &self.snapshots {
+            let mut object = snapshot.clone();
+            object.apply_transform(map);
+            self.layers.layer_mut(*layer).object = object;
+        }
+        edge.on_resized(&to)
+    }
+
+    /// Remembers the selected objects as they were before the current gesture modified them,
+    /// so that the whole gesture can be recorded as a single history entry on mouse up.
+    fn take_snapshots(&mut self) {
+        if self.snapshots.is_empty() {
+            self.snapshots = self
+                .selection
+                .iter()
+                .map(|&layer| (layer, self.layers.layer(layer).object.clone()))
+                .collect();
+        }
+    }
+
+    fn point_over_selection_edge(&self, point: Point) -> Option<Edge> {
+        let point = match self.selection[..] {
+            [layer] => self.layers.layer(layer).object.to_local(point),
+            _ => point,
+        };
+        self.selection_edges()
+            .into_iter()
+            .find(|edge| edge.is_point_over(point, self.viewport.zoom()))
+    }
+
+    fn point_over_rotate_handle(&self, point: Point) -> bool {
+        match self.selection[..] {
+            [layer] => {
+                let zoom = self.viewport.zoom();
+                self.layers
+                    .layer(layer)
+                    .object
+                    .is_point_over_rotate_handle(point, zoom)
             }
-            Some(LayerState::Resize { edge, .. }) => edge.set_cursor(canvas.canvas()),
-            _ => set_default_cursor(canvas),
+            _ => false,
         }
     }
 
-    fn last_item(&self) -> usize {
-        self.layers.len() - 1
+    fn set_cursor(&self, point: Point, surface: &dyn Surface) {
+        let maybe_edge = match self.state {
+            None if self.pan_key_held => return surface.set_cursor("grab"),
+            None if self.point_over_rotate_handle(point) => return surface.set_cursor("grab"),
+            None => self.point_over_selection_edge(point),
+            Some(LayerState::Resize { edge, .. }) => Some(edge),
+            Some(LayerState::Rotate { .. }) | Some(LayerState::Pan { .. }) => {
+                return surface.set_cursor("grabbing")
+            }
+            _ => None,
+        };
+        if let Some(edge) = maybe_edge {
+            surface.set_cursor(edge.cursor())
+        } else {
+            surface.set_cursor(self.tool.cursor())
+        }
     }
 
-    fn find_layer_from_point(&self, point: Point, canvas: &Canvas) -> Option<usize> {
-        self.layers
-            .iter()
+    fn next_id(&mut self) -> LayerId {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    /// Keeps the id of `layer`, which is about to join the stack, unless a layer there has it
+    /// already, as the children of copies of a group do.
+    fn claim_id(&mut self, layer: &mut Layer) {
+        if self.layers.get(layer.id).is_some() {
+            layer.id = self.next_id();
+        }
+        self.last_id = self.last_id.max(layer.id);
+    }
+
+    /// Finds the topmost layer under `point`, passing over hidden and locked ones.
+    fn find_layer_from_point(&self, point: Point) -> Option<LayerId> {
+        let tolerance = HIT_TOLERANCE / self.viewport.zoom();
+        let near = Rect::new(point, point).inflate(tolerance);
+        self.stacked(self.index.query(&near))
+            .into_iter()
             .rev()
-            .position(|layer| layer.object.is_point_over(canvas.context(), point))
-            .map(|idx| self.last_item() - idx)
+            .find(|&layer| {
+                let layer = self.layers.layer(layer);
+                layer.selectable() && layer.object.is_point_over(point, tolerance)
+            })
+    }
+
+    /// Finds every layer whose bounds intersect `area`, bottom to top, except hidden and locked
+    /// ones.
+    fn find_layers_in_area(&self, area: &Rect) -> Vec<LayerId> {
+        let mut layers = self.stacked(self.index.query(area));
+        layers.retain(|&layer| {
+            let layer = self.layers.layer(layer);
+            layer.selectable() && layer.object.world_bounds().intersects(area)
+        });
+        layers
+    }
+
+    /// Puts `layers` in stacking order, bottom to top.
+    fn stacked(&self, mut layers: Vec<LayerId>) -> Vec<LayerId> {
+        layers.sort_unstable_by_key(|&layer| self.layers.position(layer));
+        layers
     }
 }
 
@@ -177,10 +1384,932 @@ impl From<Layers> for Rc<RefCell<Layers>> {
     }
 }
 
-fn set_default_cursor(canvas: &Canvas) {
-    canvas
-        .canvas()
-        .style()
-        .set_property("cursor", "auto")
-        .unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::render;
+    use crate::surface::DrawOp;
+    use crate::surface::Recorder;
+    use crate::testing::double_click;
+    use crate::testing::drag;
+    use crate::testing::drag_with;
+    use crate::testing::draw;
+    use crate::testing::hover;
+    use crate::testing::hover_with;
+    use crate::testing::key;
+    use crate::testing::key_up;
+    use crate::testing::press;
+    use crate::testing::release;
+    use crate::testing::shift_click;
+    use crate::testing::two_rects;
+    use std::cell::Cell;
+
+    /// Draws the layers like the render loop would once they changed.
+    fn rendered(layers: &mut Layers, surface: &Recorder) -> Vec<DrawOp> {
+        assert!(!matches!(layers.take_damage(), Damage::None));
+        render(surface, layers);
+        surface.ops()
+    }
+
+    #[test]
+    fn creates_rect_by_dragging_on_empty_space() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = Layers::new();
+        draw(
+            &mut layers,
+            &surface,
+            ObjectKind::Rect,
+            (10.0, 10.0),
+            (50.0, 30.0),
+        );
+
+        assert_eq!(layers.layers().len(), 1);
+        assert_eq!(layers.selection(), &[1]);
+        assert!(rendered(&mut layers, &surface).contains(&DrawOp::Rect {
+            x: 10.0,
+            y: 10.0,
+            width: 40.0,
+            height: 20.0
+        }));
+    }
+
+    #[test]
+    fn relocates_active_layer_and_undoes_as_one_step() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = Layers::new();
+        draw(
+            &mut layers,
+            &surface,
+            ObjectKind::Rect,
+            (10.0, 10.0),
+            (50.0, 30.0),
+        );
+        layers.set_tool(Tool::Select);
+
+        press(&mut layers, &surface, (20.0, 20.0));
+        hover(&mut layers, &surface, (25.0, 20.0));
+        hover(&mut layers, &surface, (30.0, 25.0));
+        release(&mut layers, &surface, (30.0, 25.0));
+        assert_eq!(layers.layers()[0].object.top_left().x, 20.0);
+
+        layers.undo();
+        assert_eq!(layers.layers()[0].object.top_left().x, 10.0);
+        layers.undo();
+        assert!(layers.layers().is_empty());
+    }
+
+    #[test]
+    fn sets_resize_cursor_over_active_edge() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = Layers::new();
+        draw(
+            &mut layers,
+            &surface,
+            ObjectKind::Rect,
+            (10.0, 10.0),
+            (50.0, 30.0),
+        );
+
+        hover(&mut layers, &surface, (50.0, 30.0));
+
+        assert_eq!(surface.cursor(), "nwse-resize");
+    }
+
+    #[test]
+    fn outlines_topmost_layer_under_pointer() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = Layers::new();
+        draw(
+            &mut layers,
+            &surface,
+            ObjectKind::Rect,
+            (10.0, 10.0),
+            (50.0, 30.0),
+        );
+        drag(&mut layers, &surface, (100.0, 10.0), (150.0, 30.0));
+        layers.set_tool(Tool::Select);
+        drag(&mut layers, &surface, (300.0, 300.0), (300.0, 300.0));
+
+        hover(&mut layers, &surface, (20.0, 20.0));
+
+        assert_eq!(layers.outlined_layer(), &Some(1));
+    }
+
+    #[test]
+    fn creates_ellipse_when_chosen() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = Layers::new();
+        layers.set_tool(Tool::Create(ObjectKind::Ellipse));
+
+        drag(&mut layers, &surface, (10.0, 10.0), (50.0, 30.0));
+
+        assert!(rendered(&mut layers, &surface).contains(&DrawOp::Ellipse {
+            x: 30.0,
+            y: 20.0,
+            radius_x: 20.0,
+            radius_y: 10.0
+        }));
+    }
+
+    #[test]
+    fn marquee_selects_layer_on_empty_space_drag() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = Layers::new();
+        draw(
+            &mut layers,
+            &surface,
+            ObjectKind::Rect,
+            (10.0, 10.0),
+            (50.0, 30.0),
+        );
+        drag(&mut layers, &surface, (100.0, 10.0), (150.0, 30.0));
+        layers.set_tool(Tool::Select);
+
+        drag(&mut layers, &surface, (0.0, 0.0), (20.0, 50.0));
+
+        assert_eq!(layers.layers().len(), 2);
+        assert_eq!(layers.selection(), &[1]);
+    }
+
+    #[test]
+    fn pan_tool_scrolls_view_and_leaves_document_untouched() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = Layers::new();
+        draw(
+            &mut layers,
+            &surface,
+            ObjectKind::Rect,
+            (10.0, 10.0),
+            (50.0, 30.0),
+        );
+        layers.set_tool(Tool::Pan);
+
+        drag(&mut layers, &surface, (20.0, 20.0), (60.0, 60.0));
+
+        assert_eq!(layers.layers().len(), 1);
+        assert_eq!(layers.layers()[0].object.left(), 10.0);
+        assert_eq!(
+            layers.viewport().to_world(Point { x: 60.0, y: 60.0 }),
+            Point { x: 20.0, y: 20.0 }
+        );
+        assert_eq!(surface.cursor(), "grab");
+    }
+
+    #[test]
+    fn space_drag_pans_instead_of_moving_layers() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+        let modifiers = Modifiers::default();
+
+        key(&mut layers, &surface, " ", modifiers);
+        drag(&mut layers, &surface, (20.0, 20.0), (30.0, 20.0));
+        key_up(&mut layers, &surface, " ", modifiers);
+
+        assert_eq!(layers.layers()[0].object.left(), 10.0);
+        assert_eq!(
+            layers.viewport().to_screen(Point { x: 10.0, y: 10.0 }),
+            Point { x: 20.0, y: 10.0 }
+        );
+        assert_eq!(surface.cursor(), "auto");
+    }
+
+    #[test]
+    fn wheel_zooms_around_pointer_and_maps_events_into_document() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = Layers::new();
+        let point = Point { x: 100.0, y: 100.0 };
+        let modifiers = Modifiers::default();
+
+        let delta = -2.0_f64.ln() / WHEEL_ZOOM_STEP.ln();
+        layers.on_event(Event::Wheel { point, delta, modifiers }, &surface);
+        draw(
+            &mut layers,
+            &surface,
+            ObjectKind::Rect,
+            (100.0, 100.0),
+            (140.0, 120.0),
+        );
+
+        assert!((layers.viewport().zoom() - 2.0).abs() < 1e-9);
+        let object = &layers.layers()[0].object;
+        assert!((object.right() - 120.0).abs() < 1e-9);
+        assert!((object.bottom() - 110.0).abs() < 1e-9);
+        // Outlines keep their width on screen
+        assert!(rendered(&mut layers, &surface).iter().any(|op| matches!(
+            op,
+            DrawOp::Stroke { line_width, .. } if (line_width - 1.5).abs() < 1e-9
+        )));
+    }
+
+    #[test]
+    fn hit_tests_layers_where_they_are_now() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+
+        drag(&mut layers, &surface, (20.0, 20.0), (620.0, 320.0));
+        key(&mut layers, &surface, "Delete", Modifiers::default());
+        key(
+            &mut layers,
+            &surface,
+            "z",
+            Modifiers { ctrl: true, ..Modifiers::default() },
+        );
+
+        assert_eq!(
+            layers.find_layer_from_point(Point { x: 20.0, y: 20.0 }),
+            None
+        );
+        assert_eq!(
+            layers.find_layer_from_point(Point { x: 620.0, y: 320.0 }),
+            Some(1)
+        );
+        assert_eq!(
+            layers.find_layer_from_point(Point { x: 120.0, y: 20.0 }),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn batches_changes_until_next_render() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+        assert!(matches!(layers.take_damage(), Damage::Everything));
+
+        hover(&mut layers, &surface, (300.0, 300.0));
+        assert!(matches!(layers.take_damage(), Damage::None));
+
+        key(&mut layers, &surface, "ArrowRight", Modifiers::default());
+        key(&mut layers, &surface, "ArrowRight", Modifiers::default());
+        assert!(matches!(layers.take_damage(), Damage::Everything));
+        assert!(matches!(layers.take_damage(), Damage::None));
+    }
+
+    #[test]
+    fn asks_for_a_frame_only_after_a_change() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+        let frames = Rc::new(Cell::new(0));
+        let requested = frames.clone();
+        layers.set_on_change(move || requested.set(requested.get() + 1));
+
+        hover(&mut layers, &surface, (300.0, 300.0));
+        assert_eq!(frames.get(), 0);
+
+        layers.set_layer_meta(1, |meta| meta.name = "Sun".to_string());
+        assert!(frames.get() > 0);
+    }
+
+    #[test]
+    fn hover_damages_only_around_outlined_layer() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+        layers.take_damage();
+
+        hover(&mut layers, &surface, (20.0, 20.0));
+
+        let Damage::Area(area) = layers.take_damage() else {
+            panic!("expected a damaged area");
+        };
+        // The rect, its stroke and room for the outline
+        assert_eq!(area.top_left(), Point { x: -31.0, y: -31.0 });
+        assert_eq!(area.bottom_right(), Point { x: 91.0, y: 71.0 });
+    }
+
+    #[test]
+    fn keeps_view_centered_on_resize() {
+        let mut layers = two_rects(&Recorder::new(800.0, 400.0));
+        let surface = Recorder::new(1000.0, 300.0);
+
+        layers.on_resize(800.0, 400.0, &surface);
+
+        assert_eq!(
+            layers.viewport().to_world(Point { x: 500.0, y: 150.0 }),
+            Point { x: 400.0, y: 200.0 }
+        );
+        assert!(rendered(&mut layers, &surface).contains(&DrawOp::Rect {
+            x: 10.0,
+            y: 10.0,
+            width: 40.0,
+            height: 20.0
+        }));
+    }
+
+    #[test]
+    fn zooms_to_fit_every_layer() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+
+        layers.zoom_to_fit(&surface);
+
+        // The 140 pixel wide selection fills the canvas but for the padding
+        let viewport = layers.viewport();
+        assert_eq!(viewport.zoom(), 760.0 / 140.0);
+        let center = viewport.to_screen(Point { x: 80.0, y: 20.0 });
+        assert!((center.x - 400.0).abs() < 1e-9 && (center.y - 200.0).abs() < 1e-9);
+        assert!(rendered(&mut layers, &surface)
+            .iter()
+            .any(|op| matches!(op, DrawOp::Transform { .. })));
+    }
+
+    #[test]
+    fn updates_selected_style_as_one_undoable_step() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = Layers::new();
+        draw(
+            &mut layers,
+            &surface,
+            ObjectKind::Rect,
+            (10.0, 10.0),
+            (50.0, 30.0),
+        );
+
+        layers.update_style(|style| style.fill = Some("red".to_string()));
+
+        assert!(
+            rendered(&mut layers, &surface).contains(&DrawOp::Fill { color: "red".to_string() })
+        );
+        layers.undo();
+        assert_eq!(
+            layers.selected_style().unwrap().fill.as_deref(),
+            Some("#000000")
+        );
+    }
+
+    #[test]
+    fn keeps_style_during_a_gesture() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+        press(&mut layers, &surface, (20.0, 20.0));
+        hover(&mut layers, &surface, (30.0, 20.0));
+
+        layers.update_style(|style| style.fill = Some("red".to_string()));
+        release(&mut layers, &surface, (30.0, 20.0));
+        layers.undo();
+
+        assert_eq!(layers.layers()[0].object.left(), 10.0);
+        assert_eq!(
+            layers.layers()[0].object.style().fill.as_deref(),
+            Some("#000000")
+        );
+    }
+
+    #[test]
+    fn resizes_from_center_with_locked_aspect_ratio() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = Layers::new();
+        draw(
+            &mut layers,
+            &surface,
+            ObjectKind::Rect,
+            (10.0, 10.0),
+            (50.0, 30.0),
+        );
+        let modifiers = Modifiers { shift: true, alt: true, ..Modifiers::default() };
+
+        drag_with(&mut layers, &surface, (50.0, 20.0), (70.0, 0.0), modifiers);
+
+        let object = &layers.layers()[0].object;
+        assert_eq!(object.top_left(), Point { x: -10.0, y: 0.0 });
+        assert_eq!(object.bottom_right(), Point { x: 70.0, y: 40.0 });
+    }
+
+    #[test]
+    fn flips_handle_when_dragged_past_opposite_side_with_shift() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = Layers::new();
+        draw(
+            &mut layers,
+            &surface,
+            ObjectKind::Rect,
+            (10.0, 10.0),
+            (50.0, 30.0),
+        );
+        let shift = Modifiers { shift: true, ..Modifiers::default() };
+
+        press(&mut layers, &surface, (50.0, 30.0));
+        hover_with(&mut layers, &surface, (0.0, 35.0), shift);
+
+        // The bottom right handle ends up on the bottom left
+        assert_eq!(surface.cursor(), "nesw-resize");
+        let object = &layers.layers()[0].object;
+        assert_eq!(object.top_left(), Point { x: -40.0, y: 10.0 });
+        assert_eq!(object.bottom_right(), Point { x: 10.0, y: 35.0 });
+    }
+
+    #[test]
+    fn rotates_with_handle_snapping_under_shift() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = Layers::new();
+        draw(
+            &mut layers,
+            &surface,
+            ObjectKind::Rect,
+            (10.0, 10.0),
+            (50.0, 30.0),
+        );
+        let modifiers = Modifiers { shift: true, ..Modifiers::default() };
+
+        assert!(layers.point_over_rotate_handle(Point { x: 30.0, y: -14.0 }));
+        drag_with(
+            &mut layers,
+            &surface,
+            (30.0, -14.0),
+            (61.0, -8.0),
+            modifiers,
+        );
+
+        let rotation = layers.layers()[0].object.rotation();
+        assert!((rotation - PI / 4.0).abs() < 1e-9);
+        assert!(rendered(&mut layers, &surface)
+            .iter()
+            .any(|op| matches!(op, DrawOp::Transform { .. })));
+        layers.undo();
+        assert_eq!(layers.layers()[0].object.rotation(), 0.0);
+    }
+
+    #[test]
+    fn flips_selection_around_its_center() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+        key(
+            &mut layers,
+            &surface,
+            "a",
+            Modifiers { ctrl: true, ..Modifiers::default() },
+        );
+
+        let shift = Modifiers { shift: true, ..Modifiers::default() };
+        key(&mut layers, &surface, "H", shift);
+
+        assert_eq!(layers.layers()[0].object.world_bounds().left(), 110.0);
+        assert_eq!(layers.layers()[1].object.world_bounds().left(), 10.0);
+        layers.undo();
+        assert_eq!(layers.layers()[0].object.left(), 10.0);
+    }
+
+    #[test]
+    fn deletes_selection_and_undoes_with_keys() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+        let ctrl = Modifiers { ctrl: true, ..Modifiers::default() };
+
+        key(&mut layers, &surface, "a", ctrl);
+        assert_eq!(layers.selection(), &[1, 2]);
+        key(&mut layers, &surface, "Delete", Modifiers::default());
+        assert!(layers.layers().is_empty());
+
+        key(&mut layers, &surface, "z", ctrl);
+        assert_eq!(layers.layers()[0].id, 1);
+        assert_eq!(layers.layers()[1].id, 2);
+        assert_eq!(layers.selection().len(), 2);
+    }
+
+    #[test]
+    fn keeps_referring_to_layers_by_id_when_others_are_removed() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+        shift_click(&mut layers, &surface, 20.0, 20.0);
+        key(&mut layers, &surface, "ArrowRight", Modifiers::default());
+
+        layers.set_selection(&[1, 7]);
+        key(&mut layers, &surface, "Delete", Modifiers::default());
+        assert_eq!(layers.layers().position(2), Some(0));
+        layers.set_selection(&[2]);
+        key(&mut layers, &surface, "ArrowDown", Modifiers::default());
+
+        let ctrl = Modifiers { ctrl: true, ..Modifiers::default() };
+        key(&mut layers, &surface, "z", ctrl);
+        key(&mut layers, &surface, "z", ctrl);
+        key(&mut layers, &surface, "z", ctrl);
+        assert_eq!(layers.layers()[0].id, 1);
+        assert_eq!(
+            layers.layers()[1].object.top_left(),
+            Point { x: 100.0, y: 10.0 }
+        );
+        assert_eq!(
+            layers.find_layer_from_point(Point { x: 20.0, y: 20.0 }),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn reorders_selection_and_undoes_as_one_step() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+        draw(
+            &mut layers,
+            &surface,
+            ObjectKind::Rect,
+            (30.0, 20.0),
+            (80.0, 40.0),
+        );
+        let ids = |layers: &Layers| -> Vec<LayerId> {
+            layers.layers().iter().map(|layer| layer.id).collect()
+        };
+        let ctrl = Modifiers { ctrl: true, ..Modifiers::default() };
+        let ctrl_shift = Modifiers { shift: true, ..ctrl };
+
+        layers.set_selection(&[1]);
+        key(&mut layers, &surface, "]", ctrl);
+        assert_eq!(ids(&layers), vec![2, 1, 3]);
+        key(&mut layers, &surface, "}", ctrl_shift);
+        assert_eq!(ids(&layers), vec![2, 3, 1]);
+        assert_eq!(
+            layers.find_layer_from_point(Point { x: 40.0, y: 25.0 }),
+            Some(1)
+        );
+
+        layers.set_selection(&[3, 1]);
+        key(&mut layers, &surface, "[", ctrl);
+        assert_eq!(ids(&layers), vec![3, 1, 2]);
+        key(&mut layers, &surface, "[", ctrl);
+        assert_eq!(ids(&layers), vec![3, 1, 2]);
+        assert_eq!(layers.selection(), &[3, 1]);
+
+        layers.undo();
+        assert_eq!(ids(&layers), vec![2, 3, 1]);
+        layers.undo();
+        layers.undo();
+        assert_eq!(ids(&layers), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn duplicates_selection_offset_and_selected() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+        let ctrl = Modifiers { ctrl: true, ..Modifiers::default() };
+        key(&mut layers, &surface, "a", ctrl);
+
+        key(&mut layers, &surface, "d", ctrl);
+
+        assert_eq!(layers.layers().len(), 4);
+        assert_eq!(layers.selection(), &[3, 4]);
+        assert_eq!(
+            layers.layers().layer(4).object.top_left(),
+            Point { x: 110.0, y: 20.0 }
+        );
+        layers.undo();
+        assert_eq!(layers.layers().len(), 2);
+    }
+
+    #[test]
+    fn cuts_and_pastes_through_clipboard() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+
+        layers.copy_selection();
+        layers.delete_selection();
+        assert_eq!(layers.layers().len(), 1);
+        layers.paste_clipboard();
+        layers.paste_clipboard();
+
+        let ids: Vec<_> = layers.layers().iter().map(|layer| layer.id).collect();
+        assert_eq!(ids, vec![1, 3, 4]);
+        assert_eq!(layers.selection(), &[4]);
+        // Each paste lands further along instead of on top of the one before
+        assert_eq!(layers.layers()[1].object.left(), 110.0);
+        assert_eq!(layers.layers()[2].object.left(), 120.0);
+        assert_eq!(
+            layers.find_layer_from_point(Point { x: 140.0, y: 35.0 }),
+            Some(4)
+        );
+        layers.undo();
+        layers.undo();
+        layers.undo();
+        assert_eq!(layers.layers().len(), 2);
+    }
+
+    #[test]
+    fn cascades_repeated_pastes() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+        layers.set_selection(&[2]);
+        layers.copy_selection();
+
+        for _ in 0..3 {
+            layers.paste_clipboard();
+        }
+        layers.set_selection(&[1]);
+        layers.copy_selection();
+        layers.paste_clipboard();
+
+        let lefts: Vec<_> = layers.layers()[2..]
+            .iter()
+            .map(|layer| layer.object.left())
+            .collect();
+        assert_eq!(lefts, vec![110.0, 120.0, 130.0, 20.0]);
+    }
+
+    #[test]
+    fn groups_and_ungroups_as_undoable_steps() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+        let ctrl = Modifiers { ctrl: true, ..Modifiers::default() };
+        key(&mut layers, &surface, "a", ctrl);
+
+        key(&mut layers, &surface, "g", ctrl);
+        assert_eq!(layers.layers().len(), 1);
+        assert_eq!(layers.selection(), &[3]);
+        drag(&mut layers, &surface, (120.0, 20.0), (130.0, 30.0));
+        assert_eq!(
+            layers.layers()[0].object.bounds().top_left(),
+            Point { x: 20.0, y: 20.0 }
+        );
+
+        key(
+            &mut layers,
+            &surface,
+            "G",
+            Modifiers { shift: true, ..ctrl },
+        );
+        // The children come back as the layers they were
+        assert_eq!(layers.selection(), &[1, 2]);
+        assert_eq!(layers.layers()[1].object.left(), 110.0);
+        layers.undo();
+        layers.undo();
+        layers.undo();
+        assert_eq!(layers.layers().len(), 2);
+        assert_eq!(layers.layers()[0].id, 1);
+        assert_eq!(layers.layers()[1].object.left(), 100.0);
+    }
+
+    #[test]
+    fn edits_group_children_after_double_click() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+        layers.select_all();
+        layers.group_selection();
+
+        double_click(&mut layers, &surface, (120.0, 20.0));
+        assert_eq!(layers.layers().len(), 2);
+        assert_eq!(layers.selection(), &[2]);
+        drag(&mut layers, &surface, (120.0, 20.0), (120.0, 60.0));
+        assert_eq!(layers.document().len(), 1);
+        assert_eq!(layers.document()[0].object.bottom(), 70.0);
+        assert_eq!(layers.drawn_layers().len(), 2);
+
+        // Clicking away leaves the group, which takes the edit along as one step
+        drag(&mut layers, &surface, (400.0, 300.0), (400.0, 300.0));
+        assert_eq!(layers.layers().len(), 1);
+        assert!(layers.layers()[0].object.as_group().is_some());
+        layers.undo();
+        assert_eq!(layers.layers()[0].object.bottom(), 30.0);
+        layers.undo();
+        assert_eq!(layers.layers().len(), 2);
+    }
+
+    #[test]
+    fn repaints_the_box_around_an_entered_group_as_it_grows() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+        layers.select_all();
+        layers.group_selection();
+        double_click(&mut layers, &surface, (120.0, 20.0));
+        press(&mut layers, &surface, (120.0, 20.0));
+        layers.take_damage();
+
+        hover(&mut layers, &surface, (620.0, 320.0));
+
+        let Damage::Area(area) = layers.take_damage() else {
+            panic!("expected a damaged area");
+        };
+        let grown = layers.entered_bounds().unwrap();
+        assert_eq!(grown.bottom_right(), Point { x: 650.0, y: 330.0 });
+        assert!(area.left() <= grown.left() && area.top() <= grown.top());
+        assert!(area.right() >= grown.right() && area.bottom() >= grown.bottom());
+    }
+
+    #[test]
+    fn removes_group_once_its_last_child_is_deleted() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+        layers.select_all();
+        layers.group_selection();
+        double_click(&mut layers, &surface, (120.0, 20.0));
+
+        layers.select_all();
+        layers.delete_selection();
+
+        assert!(layers.document().is_empty());
+        assert!(layers.entered_bounds().is_none());
+        layers.undo();
+        let group = layers.document()[0].object.as_group().unwrap();
+        assert_eq!(group.children().len(), 2);
+    }
+
+    #[test]
+    fn keeps_names_and_flags_of_grouped_layers() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+        layers.set_layer_meta(1, |meta| meta.name = "Left".to_string());
+        layers.select_all();
+        layers.group_selection();
+
+        double_click(&mut layers, &surface, (120.0, 20.0));
+        assert_eq!(layers.layers().layer(1).meta.name, "Left");
+        layers.set_layer_meta(2, |meta| meta.hidden = true);
+        drag(&mut layers, &surface, (400.0, 300.0), (400.0, 300.0));
+        double_click(&mut layers, &surface, (20.0, 20.0));
+        assert!(layers.layers().layer(2).meta.hidden);
+        drag(&mut layers, &surface, (400.0, 300.0), (400.0, 300.0));
+
+        layers.select_all();
+        layers.ungroup_selection();
+        layers.undo();
+        layers.redo();
+        let metas: Vec<_> = layers
+            .layers()
+            .iter()
+            .map(|layer| (layer.id, &layer.meta))
+            .collect();
+        assert_eq!(
+            metas[0],
+            (
+                1,
+                &LayerMeta { name: "Left".to_string(), ..LayerMeta::default() }
+            )
+        );
+        assert_eq!(
+            metas[1],
+            (2, &LayerMeta { hidden: true, ..LayerMeta::default() })
+        );
+        assert_eq!(layers.selection(), &[1]);
+    }
+
+    #[test]
+    fn leaves_untouched_group_without_an_undo_step() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+        layers.select_all();
+        layers.group_selection();
+
+        double_click(&mut layers, &surface, (120.0, 20.0));
+        drag(&mut layers, &surface, (120.0, 20.0), (120.0, 60.0));
+        layers.undo();
+        drag(&mut layers, &surface, (400.0, 300.0), (400.0, 300.0));
+        layers.undo();
+
+        // Undoing the grouping is all that was left
+        assert_eq!(layers.layers().len(), 2);
+    }
+
+    #[test]
+    fn renumbers_duplicate_ids_on_load() {
+        let surface = Recorder::new(800.0, 400.0);
+        let objects = two_rects(&surface);
+        let layers_with_id = |id| {
+            objects
+                .layers()
+                .iter()
+                .map(|layer| Layer::new(id, layer.object.clone()))
+                .collect::<Vec<_>>()
+        };
+        let mut layers = Layers::new();
+
+        layers.load(layers_with_id(4));
+
+        let ids: Vec<_> = layers.layers().iter().map(|layer| layer.id).collect();
+        assert_eq!(ids, vec![4, 5]);
+        assert_eq!(layers.layers().layer(5).object.left(), 100.0);
+    }
+
+    #[test]
+    fn sets_text_of_selected_texts_as_one_step() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+        layers.set_tool(Tool::Create(ObjectKind::Text));
+        drag(&mut layers, &surface, (10.0, 100.0), (80.0, 120.0));
+        layers.select_all();
+        assert_eq!(layers.selected_text(), Some("Text"));
+
+        layers.set_text("Title");
+        assert_eq!(layers.selected_text(), Some("Title"));
+        layers.undo();
+        assert_eq!(layers.selection(), &[3]);
+        assert_eq!(layers.selected_text(), Some("Text"));
+    }
+
+    #[test]
+    fn nudges_selection_with_arrow_keys() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+
+        key(&mut layers, &surface, "ArrowRight", Modifiers::default());
+        let shift = Modifiers { shift: true, ..Modifiers::default() };
+        key(&mut layers, &surface, "ArrowDown", shift);
+
+        assert_eq!(
+            layers.layers()[1].object.top_left(),
+            Point { x: 101.0, y: 20.0 }
+        );
+        assert_eq!(layers.layers()[0].object.left(), 10.0);
+    }
+
+    #[test]
+    fn escape_cancels_relocate() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+        let modifiers = Modifiers::default();
+
+        press(&mut layers, &surface, (20.0, 20.0));
+        hover(&mut layers, &surface, (60.0, 60.0));
+        key(&mut layers, &surface, "Escape", modifiers);
+        release(&mut layers, &surface, (60.0, 60.0));
+
+        assert_eq!(
+            layers.layers()[0].object.top_left(),
+            Point { x: 10.0, y: 10.0 }
+        );
+        assert!(layers.state().is_none());
+        layers.undo();
+        assert_eq!(layers.layers().len(), 1);
+    }
+
+    #[test]
+    fn skips_hidden_and_locked_layers_when_selecting() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+        layers.take_layers_changed();
+
+        layers.set_layer_meta(2, |meta| meta.hidden = true);
+        layers.set_layer_meta(1, |meta| meta.locked = true);
+        assert!(layers.take_layers_changed());
+        assert!(layers.selection().is_empty());
+        drag(&mut layers, &surface, (120.0, 20.0), (130.0, 30.0));
+        drag(&mut layers, &surface, (20.0, 20.0), (30.0, 30.0));
+        assert_eq!(layers.layers().layer(1).object.left(), 10.0);
+        assert_eq!(layers.layers().layer(2).object.left(), 100.0);
+        layers.select_all();
+        assert!(layers.selection().is_empty());
+        assert!(!layers.take_layers_changed());
+
+        layers.undo();
+        assert!(!layers.layers().layer(1).meta.locked);
+        drag(&mut layers, &surface, (20.0, 20.0), (20.0, 20.0));
+        assert_eq!(layers.selection(), &[1]);
+    }
+
+    #[test]
+    fn shift_click_toggles_selection() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+
+        shift_click(&mut layers, &surface, 20.0, 20.0);
+        assert_eq!(layers.selection(), &[2, 1]);
+
+        shift_click(&mut layers, &surface, 120.0, 20.0);
+        assert_eq!(layers.selection(), &[1]);
+    }
+
+    #[test]
+    fn marquee_selects_every_intersecting_layer() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+
+        drag(&mut layers, &surface, (0.0, 0.0), (200.0, 15.0));
+
+        assert_eq!(layers.selection(), &[1, 2]);
+        assert!(rendered(&mut layers, &surface).contains(&DrawOp::Rect {
+            x: 10.0,
+            y: 10.0,
+            width: 140.0,
+            height: 20.0
+        }));
+    }
+
+    #[test]
+    fn relocates_selection_together_as_one_step() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+        drag(&mut layers, &surface, (0.0, 0.0), (200.0, 15.0));
+
+        drag(&mut layers, &surface, (20.0, 20.0), (30.0, 50.0));
+
+        assert_eq!(
+            layers.layers()[0].object.top_left(),
+            Point { x: 20.0, y: 40.0 }
+        );
+        assert_eq!(
+            layers.layers()[1].object.top_left(),
+            Point { x: 110.0, y: 40.0 }
+        );
+        layers.undo();
+        assert_eq!(layers.layers()[0].object.left(), 10.0);
+        assert_eq!(layers.layers()[1].object.left(), 100.0);
+    }
+
+    #[test]
+    fn resizes_selection_by_its_combined_bounds() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = two_rects(&surface);
+        drag(&mut layers, &surface, (0.0, 0.0), (200.0, 15.0));
+
+        drag(&mut layers, &surface, (150.0, 30.0), (290.0, 50.0));
+
+        let (first, second) = (&layers.layers()[0].object, &layers.layers()[1].object);
+        assert_eq!(first.top_left(), Point { x: 10.0, y: 10.0 });
+        assert_eq!(first.bottom_right(), Point { x: 90.0, y: 50.0 });
+        assert_eq!(second.top_left(), Point { x: 190.0, y: 10.0 });
+        assert_eq!(second.bottom_right(), Point { x: 290.0, y: 50.0 });
+    }
 }
@@ -1,23 +1,42 @@
 #![allow(clippy::new_without_default)]
 
 mod canvas;
+mod document;
 mod events;
+mod history;
 pub mod iter_ext;
 mod layers;
 mod objects;
+mod renderer;
+mod shortcuts;
+mod spatial;
 mod state;
+mod style;
+pub mod surface;
+mod svg;
+#[cfg(test)]
+mod testing;
+mod tools;
+mod viewport;
 
 #[macro_use]
 mod macros;
 
+use events::Transform;
+use js_sys::Function;
+use layers::LayerId;
+use layers::LayerMeta;
+use layers::Reorder;
 use state::State;
 use std::cell::RefCell;
 use std::rc::Rc;
+use style::Style;
+use tools::Tool;
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen]
 pub struct Editor {
-    _state: Rc<RefCell<State>>,
+    state: Rc<RefCell<State>>,
 }
 
 #[wasm_bindgen]
@@ -25,18 +44,375 @@ impl Editor {
     fn new(canvas_id: &str) -> Result<Editor, JsValue> {
         let state = State::new(canvas_id)?.into();
         events::init(&state);
+        canvas::start_render_loop(&mut state.borrow_mut());
 
-        let editor = Editor { _state: state.clone() };
+        let editor = Editor { state: state.clone() };
 
         let state = state.borrow();
-        let canvas = state.canvas();
-
-        canvas.set_width(800);
-        canvas.set_height(400);
-        canvas.style().set_property("background", "white")?;
+        state.canvas.borrow_mut().set_size(800.0, 400.0)?;
+        let style = state.canvas().style();
+        style.set_property("background", "white")?;
+        // An inline canvas leaves a gap below it, which would grow its container on every resize
+        style.set_property("display", "block")?;
 
         Ok(editor)
     }
+
+    /// Activates one of `select`, `rect`, `ellipse`, `line`, `text` or `pan`.
+    #[wasm_bindgen(js_name = setTool)]
+    pub fn set_tool(&self, name: &str) -> Result<(), JsValue> {
+        let tool = Tool::from_name(name).ok_or(format!("Unknown tool '{}'", name))?;
+        let state = self.state.borrow();
+        state.layers.borrow_mut().set_tool(tool);
+        Ok(())
+    }
+
+    pub fn tool(&self) -> String {
+        self.state
+            .borrow()
+            .layers
+            .borrow()
+            .tool()
+            .name()
+            .to_string()
+    }
+
+    /// Sets the canvas to `width` by `height` CSS pixels, keeping the middle of the view in
+    /// place. The canvas follows the size of its parent element on its own until this is
+    /// called; this is for layouts where that is not what decides it.
+    pub fn resize(&self, width: f64, height: f64) -> Result<(), JsValue> {
+        self.state.borrow_mut().auto_size.set_enabled(false);
+        self.state.borrow().resize(width, height)
+    }
+
+    /// Whether the canvas follows the size of its parent element, which must hold the canvas
+    /// alone and get its size from the page.
+    #[wasm_bindgen(js_name = autoResize)]
+    pub fn auto_resize(&self) -> bool {
+        self.state.borrow().auto_size.enabled()
+    }
+
+    /// Turns following the size of the parent element on or off. Turning it on takes the
+    /// parent's current size right away.
+    #[wasm_bindgen(js_name = setAutoResize)]
+    pub fn set_auto_resize(&self, enabled: bool) {
+        let mut state = self.state.borrow_mut();
+        state.auto_size.set_enabled(enabled);
+        let container = state.canvas().parent_element();
+        if let (true, Some(observer), Some(container)) =
+            (enabled, &state.resize_observer, container)
+        {
+            // Observing again reports the current size
+            observer.unobserve(&container);
+            observer.observe(&container);
+        }
+    }
+
+    #[wasm_bindgen(js_name = toJSON)]
+    pub fn to_json(&self) -> String {
+        document::to_json(&self.state.borrow().layers.borrow())
+    }
+
+    #[wasm_bindgen(js_name = loadJSON)]
+    pub fn load_json(&self, json: &str) -> Result<(), JsValue> {
+        let layers = document::from_json(json)?;
+        self.state.borrow().layers.borrow_mut().load(layers);
+        Ok(())
+    }
+
+    /// The document as SVG, sized to fit every layer rather than to the canvas.
+    #[wasm_bindgen(js_name = exportSvg)]
+    pub fn export_svg(&self) -> String {
+        svg::export(&self.state.borrow().layers.borrow())
+    }
+
+    /// Ids of the selected layers, in the order they were selected. They are the `id`s written
+    /// by `toJSON` and keep naming the same layers while others are added, removed or moved.
+    pub fn selection(&self) -> Vec<f64> {
+        let state = self.state.borrow();
+        let layers = state.layers.borrow();
+        layers.selection().iter().map(|&id| id as f64).collect()
+    }
+
+    /// Selects the layers with the given ids. Ids of layers that do not exist are ignored.
+    #[wasm_bindgen(js_name = setSelection)]
+    pub fn set_selection(&self, ids: Vec<f64>) {
+        let ids: Vec<LayerId> = ids.into_iter().map(|id| id as LayerId).collect();
+        self.state.borrow().layers.borrow_mut().set_selection(&ids);
+    }
+
+    /// The layers being edited, bottom to top, as `{ id, name, kind, hidden, locked }`. Inside
+    /// a group they are its children. An empty `name` was not given by the user.
+    pub fn layers(&self) -> Result<JsValue, JsValue> {
+        let state = self.state.borrow();
+        let json = document::layer_list_to_json(&state.layers.borrow());
+        js_sys::JSON::parse(&json)
+    }
+
+    /// Calls `callback` without arguments whenever what `layers` returns may have changed, at
+    /// most once per frame. `null` stops the calls.
+    #[wasm_bindgen(js_name = onLayersChange)]
+    pub fn on_layers_change(&self, callback: Option<Function>) {
+        *self.state.borrow().on_layers_change.borrow_mut() = callback;
+    }
+
+    #[wasm_bindgen(js_name = setLayerName)]
+    pub fn set_layer_name(&self, id: f64, name: String) {
+        self.set_layer_meta(id, |meta| meta.name = name);
+    }
+
+    /// Hides or shows a layer. Hidden layers are not drawn and cannot be selected.
+    #[wasm_bindgen(js_name = setLayerHidden)]
+    pub fn set_layer_hidden(&self, id: f64, hidden: bool) {
+        self.set_layer_meta(id, |meta| meta.hidden = hidden);
+    }
+
+    /// Locks or unlocks a layer. Locked layers are drawn, but cannot be selected on the canvas.
+    #[wasm_bindgen(js_name = setLayerLocked)]
+    pub fn set_layer_locked(&self, id: f64, locked: bool) {
+        self.set_layer_meta(id, |meta| meta.locked = locked);
+    }
+
+    /// Removes the selected layers.
+    #[wasm_bindgen(js_name = deleteSelection)]
+    pub fn delete_selection(&self) {
+        self.state.borrow().layers.borrow_mut().delete_selection();
+    }
+
+    /// Copies the selected layers next to them and selects the copies.
+    pub fn duplicate(&self) {
+        self.state
+            .borrow()
+            .layers
+            .borrow_mut()
+            .duplicate_selection();
+    }
+
+    /// Copies the selected layers to the editor's clipboard. Returns them as a JSON document
+    /// for `paste` in another editor, or `undefined` when nothing is selected.
+    pub fn copy(&self) -> Option<String> {
+        let state = self.state.borrow();
+        let mut layers = state.layers.borrow_mut();
+        if layers.selection().is_empty() {
+            return None;
+        }
+        layers.copy_selection();
+        Some(document::objects_to_json(layers.clipboard()))
+    }
+
+    /// Like `copy`, but also removes the selected layers.
+    pub fn cut(&self) -> Option<String> {
+        let json = self.copy()?;
+        self.delete_selection();
+        Some(json)
+    }
+
+    /// Adds the layers of a JSON document from `copy`, or without one those on the editor's
+    /// clipboard, slightly offset from where they were copied.
+    pub fn paste(&self, json: Option<String>) -> Result<(), JsValue> {
+        let state = self.state.borrow();
+        let mut layers = state.layers.borrow_mut();
+        match json {
+            Some(json) => {
+                let pasted = document::from_json(&json)?;
+                layers.paste(pasted.into_iter().map(|layer| layer.object).collect());
+            }
+            None => layers.paste_clipboard(),
+        }
+        Ok(())
+    }
+
+    /// Puts the selected layers into a group.
+    pub fn group(&self) {
+        self.state.borrow().layers.borrow_mut().group_selection();
+    }
+
+    /// Replaces the selected groups with their children.
+    pub fn ungroup(&self) {
+        self.state.borrow().layers.borrow_mut().ungroup_selection();
+    }
+
+    /// Moves the selected layers one step up the stack.
+    #[wasm_bindgen(js_name = bringForward)]
+    pub fn bring_forward(&self) {
+        self.reorder_selection(Reorder::Forward);
+    }
+
+    /// Moves the selected layers one step down the stack.
+    #[wasm_bindgen(js_name = sendBackward)]
+    pub fn send_backward(&self) {
+        self.reorder_selection(Reorder::Backward);
+    }
+
+    /// Moves the selected layers above all others.
+    #[wasm_bindgen(js_name = bringToFront)]
+    pub fn bring_to_front(&self) {
+        self.reorder_selection(Reorder::ToFront);
+    }
+
+    /// Moves the selected layers below all others.
+    #[wasm_bindgen(js_name = sendToBack)]
+    pub fn send_to_back(&self) {
+        self.reorder_selection(Reorder::ToBack);
+    }
+
+    /// Mirrors the selection left to right around its center.
+    #[wasm_bindgen(js_name = flipHorizontal)]
+    pub fn flip_horizontal(&self) {
+        self.transform_selection(Transform::scale(-1.0, 1.0));
+    }
+
+    /// Mirrors the selection top to bottom around its center.
+    #[wasm_bindgen(js_name = flipVertical)]
+    pub fn flip_vertical(&self) {
+        self.transform_selection(Transform::scale(1.0, -1.0));
+    }
+
+    /// Shears the selection around its center by the angles `x` and `y`, in degrees.
+    pub fn skew(&self, x: f64, y: f64) {
+        self.transform_selection(Transform::skew(x.to_radians(), y.to_radians()));
+    }
+
+    #[wasm_bindgen(js_name = fillColor)]
+    pub fn fill_color(&self) -> Option<String> {
+        self.selected_style(|style| style.fill.clone()).flatten()
+    }
+
+    /// Sets the fill of the selected layer; `null` removes it.
+    #[wasm_bindgen(js_name = setFillColor)]
+    pub fn set_fill_color(&self, color: Option<String>) {
+        self.update_style(|style| style.fill = color.clone());
+    }
+
+    #[wasm_bindgen(js_name = strokeColor)]
+    pub fn stroke_color(&self) -> Option<String> {
+        self.selected_style(|style| style.stroke.clone()).flatten()
+    }
+
+    /// Sets the stroke of the selected layer; `null` removes it.
+    #[wasm_bindgen(js_name = setStrokeColor)]
+    pub fn set_stroke_color(&self, color: Option<String>) {
+        self.update_style(|style| style.stroke = color.clone());
+    }
+
+    #[wasm_bindgen(js_name = strokeWidth)]
+    pub fn stroke_width(&self) -> Option<f64> {
+        self.selected_style(|style| style.stroke_width)
+    }
+
+    #[wasm_bindgen(js_name = setStrokeWidth)]
+    pub fn set_stroke_width(&self, width: f64) {
+        self.update_style(|style| style.stroke_width = width.max(0.0));
+    }
+
+    pub fn opacity(&self) -> Option<f64> {
+        self.selected_style(|style| style.opacity)
+    }
+
+    #[wasm_bindgen(js_name = setOpacity)]
+    pub fn set_opacity(&self, opacity: f64) {
+        self.update_style(|style| style.opacity = opacity.clamp(0.0, 1.0));
+    }
+
+    pub fn dash(&self) -> Option<Vec<f64>> {
+        self.selected_style(|style| style.dash.clone())
+    }
+
+    /// Sets the stroke dash pattern of the selected layer; an empty array draws solid lines.
+    #[wasm_bindgen(js_name = setDash)]
+    pub fn set_dash(&self, dash: Vec<f64>) {
+        self.update_style(|style| style.dash = dash.clone());
+    }
+
+    /// Content of the selected text layer, if any.
+    pub fn text(&self) -> Option<String> {
+        let state = self.state.borrow();
+        let layers = state.layers.borrow();
+        layers.selected_text().map(str::to_string)
+    }
+
+    /// Replaces the content of the selected text layers.
+    #[wasm_bindgen(js_name = setText)]
+    pub fn set_text(&self, content: &str) {
+        self.state.borrow().layers.borrow_mut().set_text(content);
+    }
+
+    pub fn zoom(&self) -> f64 {
+        self.state.borrow().layers.borrow().viewport().zoom()
+    }
+
+    /// Zooms around the center of the canvas; 1 shows the document at its actual size.
+    #[wasm_bindgen(js_name = setZoom)]
+    pub fn set_zoom(&self, zoom: f64) {
+        let state = self.state.borrow();
+        let canvas = state.canvas.borrow();
+        state.layers.borrow_mut().set_zoom(zoom, &*canvas);
+    }
+
+    /// Zooms and scrolls so that the whole document is in view.
+    #[wasm_bindgen(js_name = zoomToFit)]
+    pub fn zoom_to_fit(&self) {
+        let state = self.state.borrow();
+        let canvas = state.canvas.borrow();
+        state.layers.borrow_mut().zoom_to_fit(&*canvas);
+    }
+
+    pub fn undo(&self) {
+        self.state.borrow().layers.borrow_mut().undo();
+    }
+
+    pub fn redo(&self) {
+        self.state.borrow().layers.borrow_mut().redo();
+    }
+
+    #[wasm_bindgen(js_name = canUndo)]
+    pub fn can_undo(&self) -> bool {
+        self.state.borrow().layers.borrow().can_undo()
+    }
+
+    #[wasm_bindgen(js_name = canRedo)]
+    pub fn can_redo(&self) -> bool {
+        self.state.borrow().layers.borrow().can_redo()
+    }
+}
+
+impl Editor {
+    fn set_layer_meta(&self, id: f64, update: impl FnOnce(&mut LayerMeta)) {
+        self.state
+            .borrow()
+            .layers
+            .borrow_mut()
+            .set_layer_meta(id as LayerId, update);
+    }
+
+    fn reorder_selection(&self, reorder: Reorder) {
+        self.state
+            .borrow()
+            .layers
+            .borrow_mut()
+            .reorder_selection(reorder);
+    }
+
+    fn transform_selection(&self, transform: Transform) {
+        self.state
+            .borrow()
+            .layers
+            .borrow_mut()
+            .transform_selection(transform);
+    }
+
+    fn selected_style<T>(&self, get: impl FnOnce(&Style) -> T) -> Option<T> {
+        self.state
+            .borrow()
+            .layers
+            .borrow()
+            .selected_style()
+            .map(get)
+    }
+
+    fn update_style(&self, update: impl Fn(&mut Style)) {
+        self.state.borrow().layers.borrow_mut().update_style(update);
+    }
 }
 
 #[wasm_bindgen]
@@ -1,16 +1,57 @@
+use crate::document::LayerData;
 use crate::events::Point;
-use web_sys::CanvasRenderingContext2d;
-use web_sys::HtmlCanvasElement;
+use crate::events::Transform;
+use crate::layers::Layer;
+use crate::renderer::transformed;
+use crate::style::Style;
+use crate::surface::Surface;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fmt;
 
 pub trait Object {
-    fn draw(&self, context: &CanvasRenderingContext2d);
-    fn draw_outline(&self, context: &CanvasRenderingContext2d);
-    fn draw_active(&self, context: &CanvasRenderingContext2d);
-    fn is_point_over(&self, context: &CanvasRenderingContext2d, point: Point) -> bool;
+    fn draw(&self, surface: &dyn Surface);
+    /// Outlines and handles keep their size on screen at any viewport `zoom`, so they are
+    /// drawn `1 / zoom` times as large in the document.
+    fn draw_outline(&self, surface: &dyn Surface, zoom: f64);
+    fn draw_active(&self, surface: &dyn Surface, zoom: f64);
+    /// Whether `point` hits the object, counting points up to `tolerance` outside its shape.
+    fn is_point_over(&self, point: Point, tolerance: f64) -> bool;
     fn edges(&self) -> Vec<Edge>;
-    fn resize(&mut self, point: Point, edge: Edge);
-    fn grab_point(&self, point: Point) -> Point;
-    fn relocate(&mut self, point: Point, grab_point: Point);
+    /// Drags the handle `edge` to `point`. Returns the handle as it is afterwards, which is of a
+    /// different kind once the drag crossed the opposite side.
+    fn resize(&mut self, point: Point, edge: Edge) -> Edge;
+    /// Moves every point that defines the object through `map`, in its local frame.
+    fn map_points(&mut self, map: &dyn Fn(Point) -> Point);
+    /// Maps the object's local frame onto the canvas. Edges, bounds and `resize` all work in
+    /// the local frame; `to_local` maps points on the canvas into it.
+    fn transform(&self) -> Transform;
+    /// Moves, turns, flips or skews the object on the canvas by `transform`.
+    fn apply_transform(&mut self, transform: Transform);
+    fn clone_box(&self) -> Box<dyn Object>;
+    fn to_data(&self) -> ObjectData;
+    /// What the object is, by the name it is tagged with in `ObjectData`.
+    fn kind(&self) -> &'static str;
+    /// The SVG element that draws this object.
+    fn to_svg(&self) -> String;
+    fn style(&self) -> &Style;
+    fn style_mut(&mut self) -> &mut Style;
+    /// Changes the style of the object, or of every object in it. Returns whether anything
+    /// changed.
+    fn update_style(&mut self, update: &dyn Fn(&mut Style)) -> bool {
+        let before = self.style().clone();
+        update(self.style_mut());
+        *self.style() != before
+    }
+    fn as_group(&self) -> Option<&Group> {
+        None
+    }
+    fn as_text(&self) -> Option<&Text> {
+        None
+    }
+    fn as_text_mut(&mut self) -> Option<&mut Text> {
+        None
+    }
     fn top(&self) -> f64;
     fn set_top(&mut self, y: f64);
     fn left(&self) -> f64;
@@ -47,14 +88,142 @@ pub trait Object {
         self.set_bottom(point.y);
         self.set_left(point.x);
     }
+    fn bounds(&self) -> Rect {
+        Rect::new(self.top_left(), self.bottom_right())
+    }
+    fn center(&self) -> Point {
+        self.bounds().center()
+    }
+    /// Clockwise rotation in radians of the local x axis on the canvas.
+    fn rotation(&self) -> f64 {
+        let transform = self.transform();
+        transform.b.atan2(transform.a)
+    }
+    /// Turns the object clockwise by `angle` radians around its center on the canvas.
+    fn rotate(&mut self, angle: f64) {
+        let center = self.to_world(self.center());
+        self.apply_transform(Transform::rotate(angle).around(center));
+    }
+    /// Moves the object by `offset` on the canvas.
+    fn translate(&mut self, offset: Point) {
+        // Moving the local geometry keeps exported coordinates readable
+        if let Some(inverse) = self.transform().invert() {
+            let offset = inverse.apply_vector(offset);
+            self.map_points(&|point| point.add(offset));
+        }
+    }
+    /// Maps a point on the canvas into the object's local frame.
+    fn to_local(&self, point: Point) -> Point {
+        match self.transform().invert() {
+            Some(inverse) => inverse.apply(point),
+            None => point,
+        }
+    }
+    fn to_world(&self, point: Point) -> Point {
+        self.transform().apply(point)
+    }
+    /// Box around the object as it is drawn, i.e. including its transform.
+    fn world_bounds(&self) -> Rect {
+        let bounds = self.bounds();
+        let corners = [
+            bounds.top_left(),
+            bounds.top_right(),
+            bounds.bottom_right(),
+            bounds.bottom_left(),
+        ]
+        .map(|corner| self.to_world(corner));
+        corners
+            .iter()
+            .map(|&corner| Rect::new(corner, corner))
+            .reduce(|a, b| a.union(&b))
+            .unwrap()
+    }
+    /// `world_bounds` grown to cover the stroke, which can stick out past the geometry.
+    fn paint_bounds(&self) -> Rect {
+        let Transform { a, b, c, d, .. } = self.transform();
+        let stretch = f64::max(a.abs() + c.abs(), b.abs() + d.abs());
+        self.world_bounds()
+            .inflate(self.style().stroke_width * stretch)
+    }
+    /// Position of the rotate handle in the local frame, above the top of the object.
+    fn rotate_handle(&self, zoom: f64) -> Point {
+        let bounds = self.bounds();
+        Point { x: bounds.center().x, y: bounds.top() - ROTATE_HANDLE_OFFSET / zoom }
+    }
+    fn is_point_over_rotate_handle(&self, point: Point, zoom: f64) -> bool {
+        let Point { x, y } = self.to_local(point).subtract(self.rotate_handle(zoom));
+        (x * x + y * y).sqrt() <= Edge::SIZE / zoom / 2.0
+    }
+    /// `resize` by a point on the canvas.
+    fn resize_in_world(&mut self, point: Point, edge: Edge) -> Edge {
+        self.resize(self.to_local(point), edge)
+    }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ObjectKind {
+    Rect,
+    Ellipse,
+    Line,
+    Text,
+}
+
+impl ObjectKind {
+    pub fn create(&self, a: Point, b: Point) -> Box<dyn Object> {
+        match self {
+            ObjectKind::Rect => Box::new(Rect::new(a, b)),
+            ObjectKind::Ellipse => Box::new(Ellipse::new(a, b)),
+            ObjectKind::Line => Box::new(Line::new(a, b)),
+            ObjectKind::Text => Box::new(Text::new(a, b, "Text")),
+        }
+    }
+}
+
+/// Serializable form of every `Object` implementation, tagged by kind.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ObjectData {
+    Rect(Rect),
+    Ellipse(Ellipse),
+    Line(Line),
+    Text(Text),
+    Group(Group),
+}
+
+impl ObjectData {
+    pub fn into_object(self) -> Box<dyn Object> {
+        match self {
+            ObjectData::Rect(rect) => Box::new(rect),
+            ObjectData::Ellipse(ellipse) => Box::new(ellipse),
+            ObjectData::Line(line) => Box::new(line),
+            ObjectData::Text(text) => Box::new(text),
+            ObjectData::Group(group) => Box::new(group),
+        }
+    }
+}
+
+/// Color of the hover and selection outlines.
+pub const OUTLINE_COLOR: &str = "#3782F7";
+const OUTLINE_WIDTH: f64 = 3.0;
+/// Distance of the rotate handle above the top of the selected object.
+const ROTATE_HANDLE_OFFSET: f64 = 24.0;
+
+impl Clone for Box<dyn Object> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Rect {
     x: f64,
     y: f64,
     width: f64,
     height: f64,
+    #[serde(default, skip_serializing_if = "Transform::is_identity")]
+    transform: Transform,
+    #[serde(default)]
+    style: Style,
 }
 
 impl Rect {
@@ -64,13 +233,20 @@ impl Rect {
         let x_end = f64::max(a.x, b.x);
         let y_end = f64::max(a.y, b.y);
 
-        Rect { x: x_start, y: y_start, width: x_end - x_start, height: y_end - y_start }
+        Rect {
+            x: x_start,
+            y: y_start,
+            width: x_end - x_start,
+            height: y_end - y_start,
+            transform: Transform::IDENTITY,
+            style: Style::default(),
+        }
     }
 
     pub fn from_center(center: Point, width: f64, height: f64) -> Rect {
         let x = center.x - width / 2.0;
         let y = center.y - height / 2.0;
-        Rect { x, y, width, height }
+        Rect { x, y, width, height, transform: Transform::IDENTITY, style: Style::default() }
     }
 
     pub fn top_left(&self) -> Point {
@@ -88,62 +264,242 @@ impl Rect {
     pub fn bottom_left(&self) -> Point {
         Point { x: self.x, y: self.y + self.height }
     }
+
+    pub fn center(&self) -> Point {
+        Point { x: self.x + self.width / 2.0, y: self.y + self.height / 2.0 }
+    }
+
+    /// ` transform="..."` attribute for the local transform, if any.
+    fn svg_transform(&self) -> String {
+        if self.transform.is_identity() {
+            return String::new();
+        }
+        let Transform { a, b, c, d, e, f } = self.transform;
+        format!(
+            r#" transform="matrix({} {} {} {} {} {})""#,
+            a, b, c, d, e, f
+        )
+    }
+
+    /// Moves a negative width or height into `x` and `y`, keeping the same area.
+    fn normalize(&mut self) {
+        if self.width < 0.0 {
+            self.x += self.width;
+            self.width = -self.width;
+        }
+        if self.height < 0.0 {
+            self.y += self.height;
+            self.height = -self.height;
+        }
+    }
+
+    /// The box after dragging `edge` to `point`. With `keep_aspect` the width to height ratio
+    /// is preserved, with `from_center` the opposite side moves by the same amount the other
+    /// way. The result is not normalized, so a negative size means the box was flipped.
+    pub fn resized(&self, point: Point, edge: Edge, keep_aspect: bool, from_center: bool) -> Rect {
+        let (dir_x, dir_y) = edge.kind.direction();
+        let center = self.center();
+        // Dragged sides move away from this point, which stays put
+        let anchor = |dir: f64, center: f64, size: f64| match from_center {
+            true => center,
+            false => center - dir * size / 2.0,
+        };
+        let factor = if from_center { 2.0 } else { 1.0 };
+        let (anchor_x, anchor_y) = (
+            anchor(dir_x, center.x, self.width),
+            anchor(dir_y, center.y, self.height),
+        );
+
+        let mut width = match dir_x {
+            0.0 => self.width,
+            _ => (point.x - anchor_x) * dir_x * factor,
+        };
+        let mut height = match dir_y {
+            0.0 => self.height,
+            _ => (point.y - anchor_y) * dir_y * factor,
+        };
+        if keep_aspect && self.width != 0.0 && self.height != 0.0 {
+            let (scale_x, scale_y) = (width / self.width, height / self.height);
+            let scale = match (dir_x, dir_y) {
+                (_, 0.0) => scale_x.abs(),
+                (0.0, _) => scale_y.abs(),
+                _ => f64::max(scale_x.abs(), scale_y.abs()),
+            };
+            width = self.width * scale * scale_x.signum();
+            height = self.height * scale * scale_y.signum();
+        }
+
+        let position = |dir: f64, center: f64, anchor: f64, size: f64| {
+            if dir == 0.0 || from_center {
+                center - size / 2.0
+            } else if dir > 0.0 {
+                anchor
+            } else {
+                anchor - size
+            }
+        };
+        Rect {
+            x: position(dir_x, center.x, anchor_x, width),
+            y: position(dir_y, center.y, anchor_y, height),
+            width,
+            height,
+            transform: self.transform,
+            style: Style::default(),
+        }
+    }
+
+    pub fn union(&self, other: &Rect) -> Rect {
+        Rect::new(
+            Point { x: f64::min(self.left(), other.left()), y: f64::min(self.top(), other.top()) },
+            Point {
+                x: f64::max(self.right(), other.right()),
+                y: f64::max(self.bottom(), other.bottom()),
+            },
+        )
+    }
+
+    /// The same box with `amount` added on every side.
+    pub fn inflate(&self, amount: f64) -> Rect {
+        let amount = Point { x: amount, y: amount };
+        Rect::new(
+            self.top_left().subtract(amount),
+            self.bottom_right().add(amount),
+        )
+    }
+
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.left() <= other.right()
+            && other.left() <= self.right()
+            && self.top() <= other.bottom()
+            && other.top() <= self.bottom()
+    }
+
+    /// Maps points inside `self` proportionally to the same place inside `to`.
+    pub fn map_onto(&self, to: &Rect) -> Transform {
+        let scale = |from: f64, to: f64| if from == 0.0 { 1.0 } else { to / from };
+        let (scale_x, scale_y) = (scale(self.width, to.width), scale(self.height, to.height));
+        Transform::translate(-self.x, -self.y)
+            .then(&Transform::scale(scale_x, scale_y))
+            .then(&Transform::translate(to.x, to.y))
+    }
 }
 
 impl Object for Rect {
-    fn draw(&self, context: &CanvasRenderingContext2d) {
-        context.begin_path();
-        context.rect(self.x, self.y, self.width, self.height);
-        context.fill();
-    }
-
-    fn draw_outline(&self, context: &CanvasRenderingContext2d) {
-        let stroke_width = 3.0;
-        let offset = stroke_width / 2.0;
-        let Rect { x, y, width, height } = self;
-        context.begin_path();
-        context.rect(
+    fn draw(&self, surface: &dyn Surface) {
+        surface.begin_path();
+        surface.rect(self.x, self.y, self.width, self.height);
+        self.style.paint(surface);
+    }
+
+    fn draw_outline(&self, surface: &dyn Surface, zoom: f64) {
+        let outline_width = OUTLINE_WIDTH / zoom;
+        let offset = outline_width / 2.0;
+        let Rect { x, y, width, height, .. } = self;
+        surface.begin_path();
+        surface.rect(
             x - offset,
             y - offset,
-            width + stroke_width,
-            height + stroke_width,
+            width + outline_width,
+            height + outline_width,
         );
-        context.set_stroke_style(&"#3782F7".into());
-        context.set_line_width(stroke_width);
-        context.stroke();
+        surface.stroke(OUTLINE_COLOR, outline_width);
     }
 
-    fn draw_active(&self, context: &CanvasRenderingContext2d) {
-        self.draw_outline(context);
+    fn draw_active(&self, surface: &dyn Surface, zoom: f64) {
+        self.draw_outline(surface, zoom);
 
         for edge in self.edges() {
-            edge.draw(context);
+            edge.draw(surface, zoom);
         }
+        draw_rotate_handle(surface, self, zoom);
     }
 
-    fn is_point_over(&self, context: &CanvasRenderingContext2d, point: Point) -> bool {
-        context.begin_path();
-        context.rect(self.x, self.y, self.width, self.height);
-        context.is_point_in_path_with_f64(point.x, point.y)
+    fn is_point_over(&self, point: Point, tolerance: f64) -> bool {
+        let point = self.to_local(point);
+        let tolerance = tolerance + self.style.stroke_overhang();
+        let left = f64::min(self.left(), self.right()) - tolerance;
+        let right = f64::max(self.left(), self.right()) + tolerance;
+        let top = f64::min(self.top(), self.bottom()) - tolerance;
+        let bottom = f64::max(self.top(), self.bottom()) + tolerance;
+        left <= point.x && point.x <= right && top <= point.y && point.y <= bottom
     }
 
     fn edges(&self) -> Vec<Edge> {
         vec![
             Edge::new(self.top_left(), EdgeKind::TopLeft),
+            Edge::new(
+                Point { x: self.x + self.width / 2.0, y: self.top() },
+                EdgeKind::Top,
+            ),
             Edge::new(self.top_right(), EdgeKind::TopRight),
+            Edge::new(
+                Point { x: self.right(), y: self.y + self.height / 2.0 },
+                EdgeKind::Right,
+            ),
             Edge::new(self.bottom_right(), EdgeKind::BottomRight),
+            Edge::new(
+                Point { x: self.x + self.width / 2.0, y: self.bottom() },
+                EdgeKind::Bottom,
+            ),
             Edge::new(self.bottom_left(), EdgeKind::BottomLeft),
+            Edge::new(
+                Point { x: self.left(), y: self.y + self.height / 2.0 },
+                EdgeKind::Left,
+            ),
         ]
     }
 
-    fn grab_point(&self, point: Point) -> Point {
-        point.subtract(self.top_left())
-    }
-
-    fn relocate(&mut self, point: Point, grab_point: Point) {
-        let Point { x, y } = point.subtract(grab_point);
+    fn map_points(&mut self, map: &dyn Fn(Point) -> Point) {
+        let Rect { x, y, width, height, .. } =
+            Rect::new(map(self.top_left()), map(self.bottom_right()));
         self.x = x;
         self.y = y;
+        self.width = width;
+        self.height = height;
+    }
+
+    fn transform(&self) -> Transform {
+        self.transform
+    }
+
+    fn apply_transform(&mut self, transform: Transform) {
+        if self.transform.is_identity() && transform.is_axis_aligned() {
+            self.map_points(&|point| transform.apply(point));
+        } else {
+            self.transform = self.transform.then(&transform);
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn Object> {
+        Box::new(self.clone())
+    }
+
+    fn to_data(&self) -> ObjectData {
+        ObjectData::Rect(self.clone())
+    }
+
+    fn kind(&self) -> &'static str {
+        "rect"
+    }
+
+    fn to_svg(&self) -> String {
+        format!(
+            r#"<rect x="{}" y="{}" width="{}" height="{}" {}{}/>"#,
+            f64::min(self.left(), self.right()),
+            f64::min(self.top(), self.bottom()),
+            self.width.abs(),
+            self.height.abs(),
+            self.style.to_svg_attributes(),
+            self.svg_transform()
+        )
+    }
+
+    fn style(&self) -> &Style {
+        &self.style
+    }
+
+    fn style_mut(&mut self) -> &mut Style {
+        &mut self.style
     }
 
     fn top(&self) -> f64 {
@@ -180,69 +536,1121 @@ impl Object for Rect {
         self.width = x - self.x;
     }
 
-    fn resize(&mut self, point: Point, edge: Edge) {
+    fn resize(&mut self, point: Point, edge: Edge) -> Edge {
         match edge.kind {
             EdgeKind::TopLeft => self.set_top_left(point),
-            // EdgeKind::Top => "auto",
+            EdgeKind::Top => self.set_top(point.y),
             EdgeKind::TopRight => self.set_top_right(point),
-            // EdgeKind::Right => "auto",
+            EdgeKind::Right => self.set_right(point.x),
             EdgeKind::BottomRight => self.set_bottom_right(point),
-            // EdgeKind::Bottom => "auto",
+            EdgeKind::Bottom => self.set_bottom(point.y),
             EdgeKind::BottomLeft => self.set_bottom_left(point),
-            // EdgeKind::Left => "auto",
+            EdgeKind::Left => self.set_left(point.x),
         }
+        let kind = edge.kind.flip(self.width < 0.0, self.height < 0.0);
+        self.normalize();
+        Edge::new(point, kind)
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+/// An ellipse inscribed in `bounds`, which also carries its style.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Ellipse {
+    #[serde(flatten)]
+    bounds: Rect,
+}
+
+impl Ellipse {
+    pub fn new(a: Point, b: Point) -> Ellipse {
+        Ellipse { bounds: Rect::new(a, b) }
+    }
+
+    pub fn center(&self) -> Point {
+        self.bounds.center()
+    }
+
+    pub fn radius_x(&self) -> f64 {
+        self.bounds.width.abs() / 2.0
+    }
+
+    pub fn radius_y(&self) -> f64 {
+        self.bounds.height.abs() / 2.0
+    }
+}
+
+impl Object for Ellipse {
+    fn draw(&self, surface: &dyn Surface) {
+        let Point { x, y } = self.center();
+        surface.begin_path();
+        surface.ellipse(x, y, self.radius_x(), self.radius_y());
+        self.style().paint(surface);
+    }
+
+    fn draw_outline(&self, surface: &dyn Surface, zoom: f64) {
+        let outline_width = OUTLINE_WIDTH / zoom;
+        let offset = outline_width / 2.0;
+        let Point { x, y } = self.center();
+        surface.begin_path();
+        surface.ellipse(x, y, self.radius_x() + offset, self.radius_y() + offset);
+        surface.stroke(OUTLINE_COLOR, outline_width);
+    }
+
+    fn draw_active(&self, surface: &dyn Surface, zoom: f64) {
+        self.draw_outline(surface, zoom);
+
+        for edge in self.edges() {
+            edge.draw(surface, zoom);
+        }
+        draw_rotate_handle(surface, self, zoom);
+    }
+
+    fn is_point_over(&self, point: Point, tolerance: f64) -> bool {
+        let tolerance = tolerance + self.style().stroke_overhang();
+        let radius_x = self.radius_x() + tolerance;
+        let radius_y = self.radius_y() + tolerance;
+        if radius_x <= 0.0 || radius_y <= 0.0 {
+            return false;
+        }
+        let Point { x, y } = self.to_local(point).subtract(self.center());
+        (x / radius_x).powi(2) + (y / radius_y).powi(2) <= 1.0
+    }
+
+    fn edges(&self) -> Vec<Edge> {
+        self.bounds.edges()
+    }
+
+    fn map_points(&mut self, map: &dyn Fn(Point) -> Point) {
+        self.bounds.map_points(map);
+    }
+
+    fn transform(&self) -> Transform {
+        self.bounds.transform()
+    }
+
+    fn apply_transform(&mut self, transform: Transform) {
+        self.bounds.apply_transform(transform);
+    }
+
+    fn clone_box(&self) -> Box<dyn Object> {
+        Box::new(self.clone())
+    }
+
+    fn to_data(&self) -> ObjectData {
+        ObjectData::Ellipse(self.clone())
+    }
+
+    fn kind(&self) -> &'static str {
+        "ellipse"
+    }
+
+    fn to_svg(&self) -> String {
+        let Point { x, y } = self.center();
+        format!(
+            r#"<ellipse cx="{}" cy="{}" rx="{}" ry="{}" {}{}/>"#,
+            x,
+            y,
+            self.radius_x(),
+            self.radius_y(),
+            self.style().to_svg_attributes(),
+            self.bounds.svg_transform()
+        )
+    }
+
+    fn style(&self) -> &Style {
+        self.bounds.style()
+    }
+
+    fn style_mut(&mut self) -> &mut Style {
+        self.bounds.style_mut()
+    }
+
+    fn top(&self) -> f64 {
+        self.bounds.top()
+    }
+
+    fn set_top(&mut self, y: f64) {
+        self.bounds.set_top(y);
+    }
+
+    fn left(&self) -> f64 {
+        self.bounds.left()
+    }
+
+    fn set_left(&mut self, x: f64) {
+        self.bounds.set_left(x);
+    }
+
+    fn bottom(&self) -> f64 {
+        self.bounds.bottom()
+    }
+
+    fn set_bottom(&mut self, y: f64) {
+        self.bounds.set_bottom(y);
+    }
+
+    fn right(&self) -> f64 {
+        self.bounds.right()
+    }
+
+    fn set_right(&mut self, x: f64) {
+        self.bounds.set_right(x);
+    }
+
+    fn resize(&mut self, point: Point, edge: Edge) -> Edge {
+        self.bounds.resize(point, edge)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Line {
+    start: Point,
+    end: Point,
+    #[serde(default = "Style::line")]
+    style: Style,
+}
+
+impl Line {
+    pub fn new(start: Point, end: Point) -> Line {
+        Line { start, end, style: Style::line() }
+    }
+
+    fn stroke_path(&self, surface: &dyn Surface) {
+        surface.begin_path();
+        surface.move_to(self.start.x, self.start.y);
+        surface.line_to(self.end.x, self.end.y);
+    }
+
+    /// The endpoint that `set_top`/`set_bottom` move; ties go to `start` for top.
+    fn top_point(&mut self) -> &mut Point {
+        if self.start.y <= self.end.y {
+            &mut self.start
+        } else {
+            &mut self.end
+        }
+    }
+
+    fn bottom_point(&mut self) -> &mut Point {
+        if self.start.y <= self.end.y {
+            &mut self.end
+        } else {
+            &mut self.start
+        }
+    }
+
+    fn left_point(&mut self) -> &mut Point {
+        if self.start.x <= self.end.x {
+            &mut self.start
+        } else {
+            &mut self.end
+        }
+    }
+
+    fn right_point(&mut self) -> &mut Point {
+        if self.start.x <= self.end.x {
+            &mut self.end
+        } else {
+            &mut self.start
+        }
+    }
+}
+
+impl Object for Line {
+    fn draw(&self, surface: &dyn Surface) {
+        self.stroke_path(surface);
+        self.style.paint(surface);
+    }
+
+    fn draw_outline(&self, surface: &dyn Surface, zoom: f64) {
+        self.stroke_path(surface);
+        surface.stroke(
+            OUTLINE_COLOR,
+            self.style.stroke_width + OUTLINE_WIDTH / zoom,
+        );
+        self.draw(surface);
+    }
+
+    fn draw_active(&self, surface: &dyn Surface, zoom: f64) {
+        self.draw_outline(surface, zoom);
+
+        for edge in self.edges() {
+            edge.draw(surface, zoom);
+        }
+        draw_rotate_handle(surface, self, zoom);
+    }
+
+    fn is_point_over(&self, point: Point, tolerance: f64) -> bool {
+        let segment = self.end.subtract(self.start);
+        let offset = point.subtract(self.start);
+        let length_squared = segment.x * segment.x + segment.y * segment.y;
+        let t = if length_squared == 0.0 {
+            0.0
+        } else {
+            ((offset.x * segment.x + offset.y * segment.y) / length_squared).clamp(0.0, 1.0)
+        };
+        let closest = Point { x: self.start.x + t * segment.x, y: self.start.y + t * segment.y };
+        let Point { x, y } = point.subtract(closest);
+        (x * x + y * y).sqrt() <= self.style.stroke_overhang() + tolerance
+    }
+
+    fn edges(&self) -> Vec<Edge> {
+        let kind = |point: Point, other: Point, is_start: bool| {
+            let top = point.y < other.y || (point.y == other.y && is_start);
+            let left = point.x < other.x || (point.x == other.x && is_start);
+            match (top, left) {
+                (true, true) => EdgeKind::TopLeft,
+                (true, false) => EdgeKind::TopRight,
+                (false, false) => EdgeKind::BottomRight,
+                (false, true) => EdgeKind::BottomLeft,
+            }
+        };
+        vec![
+            Edge::new(self.start, kind(self.start, self.end, true)),
+            Edge::new(self.end, kind(self.end, self.start, false)),
+        ]
+    }
+
+    fn map_points(&mut self, map: &dyn Fn(Point) -> Point) {
+        self.start = map(self.start);
+        self.end = map(self.end);
+    }
+
+    /// Lines stay lines under any affine transform, so it is applied to the endpoints.
+    fn transform(&self) -> Transform {
+        Transform::IDENTITY
+    }
+
+    fn apply_transform(&mut self, transform: Transform) {
+        self.map_points(&|point| transform.apply(point));
+    }
+
+    fn clone_box(&self) -> Box<dyn Object> {
+        Box::new(self.clone())
+    }
+
+    fn to_data(&self) -> ObjectData {
+        ObjectData::Line(self.clone())
+    }
+
+    fn kind(&self) -> &'static str {
+        "line"
+    }
+
+    fn to_svg(&self) -> String {
+        format!(
+            r#"<line x1="{}" y1="{}" x2="{}" y2="{}" {}/>"#,
+            self.start.x,
+            self.start.y,
+            self.end.x,
+            self.end.y,
+            self.style.to_svg_attributes()
+        )
+    }
+
+    fn style(&self) -> &Style {
+        &self.style
+    }
+
+    fn style_mut(&mut self) -> &mut Style {
+        &mut self.style
+    }
+
+    fn top(&self) -> f64 {
+        f64::min(self.start.y, self.end.y)
+    }
+
+    fn set_top(&mut self, y: f64) {
+        self.top_point().y = y;
+    }
+
+    fn left(&self) -> f64 {
+        f64::min(self.start.x, self.end.x)
+    }
+
+    fn set_left(&mut self, x: f64) {
+        self.left_point().x = x;
+    }
+
+    fn bottom(&self) -> f64 {
+        f64::max(self.start.y, self.end.y)
+    }
+
+    fn set_bottom(&mut self, y: f64) {
+        self.bottom_point().y = y;
+    }
+
+    fn right(&self) -> f64 {
+        f64::max(self.start.x, self.end.x)
+    }
+
+    fn set_right(&mut self, x: f64) {
+        self.right_point().x = x;
+    }
+
+    fn resize(&mut self, point: Point, edge: Edge) -> Edge {
+        // Handles sit on the endpoints, so dragging one just moves that endpoint
+        if self.edges()[1].kind == edge.kind {
+            self.end = point;
+            self.edges()[1]
+        } else {
+            self.start = point;
+            self.edges()[0]
+        }
+    }
+}
+
+/// Single line text filling the height of `bounds`, which also carries its style. Longer text
+/// is squeezed to the width of the box and nothing is drawn outside of it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Text {
+    #[serde(flatten)]
+    bounds: Rect,
+    content: String,
+}
+
+impl Text {
+    pub fn new(a: Point, b: Point, content: &str) -> Text {
+        Text { bounds: Rect::new(a, b), content: content.to_string() }
+    }
+
+    /// The text is sized to fill the height of its box.
+    pub fn font_size(&self) -> f64 {
+        self.bounds.height.abs()
+    }
+
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    pub fn set_content(&mut self, content: &str) {
+        self.content = content.to_string();
+    }
+}
+
+impl Object for Text {
+    fn draw(&self, surface: &dyn Surface) {
+        let Point { x, y } = self.bounds.top_left();
+        let width = self.bounds.right() - x;
+        if let Some(fill) = &self.style().fill {
+            // Descenders and glyphs wider than the font size would reach past the box
+            surface.save();
+            surface.clip(x, y, width, self.font_size());
+            surface.set_alpha(self.style().opacity);
+            surface.fill_text(&self.content, x, y, self.font_size(), width, fill);
+            surface.set_alpha(1.0);
+            surface.restore();
+        }
+    }
+
+    fn draw_outline(&self, surface: &dyn Surface, zoom: f64) {
+        self.bounds.draw_outline(surface, zoom);
+    }
+
+    fn draw_active(&self, surface: &dyn Surface, zoom: f64) {
+        self.draw_outline(surface, zoom);
+
+        for edge in self.edges() {
+            edge.draw(surface, zoom);
+        }
+        draw_rotate_handle(surface, self, zoom);
+    }
+
+    fn is_point_over(&self, point: Point, tolerance: f64) -> bool {
+        self.bounds.is_point_over(point, tolerance)
+    }
+
+    fn edges(&self) -> Vec<Edge> {
+        self.bounds.edges()
+    }
+
+    fn map_points(&mut self, map: &dyn Fn(Point) -> Point) {
+        self.bounds.map_points(map);
+    }
+
+    fn transform(&self) -> Transform {
+        self.bounds.transform()
+    }
+
+    fn apply_transform(&mut self, transform: Transform) {
+        self.bounds.apply_transform(transform);
+    }
+
+    fn clone_box(&self) -> Box<dyn Object> {
+        Box::new(self.clone())
+    }
+
+    fn to_data(&self) -> ObjectData {
+        ObjectData::Text(self.clone())
+    }
+
+    fn kind(&self) -> &'static str {
+        "text"
+    }
+
+    fn as_text(&self) -> Option<&Text> {
+        Some(self)
+    }
+
+    fn as_text_mut(&mut self) -> Option<&mut Text> {
+        Some(self)
+    }
+
+    /// Text has no stroke and is clipped to its box.
+    fn paint_bounds(&self) -> Rect {
+        self.world_bounds()
+    }
+
+    fn to_svg(&self) -> String {
+        format!(
+            r#"<text x="{}" y="{}" font-family="sans-serif" font-size="{}" dominant-baseline="text-before-edge" {}{}>{}</text>"#,
+            self.bounds.left(),
+            self.bounds.top(),
+            self.font_size(),
+            self.style().to_svg_attributes(),
+            self.bounds.svg_transform(),
+            escape_xml(&self.content)
+        )
+    }
+
+    fn style(&self) -> &Style {
+        self.bounds.style()
+    }
+
+    fn style_mut(&mut self) -> &mut Style {
+        self.bounds.style_mut()
+    }
+
+    fn top(&self) -> f64 {
+        self.bounds.top()
+    }
+
+    fn set_top(&mut self, y: f64) {
+        self.bounds.set_top(y);
+    }
+
+    fn left(&self) -> f64 {
+        self.bounds.left()
+    }
+
+    fn set_left(&mut self, x: f64) {
+        self.bounds.set_left(x);
+    }
+
+    fn bottom(&self) -> f64 {
+        self.bounds.bottom()
+    }
+
+    fn set_bottom(&mut self, y: f64) {
+        self.bounds.set_bottom(y);
+    }
+
+    fn right(&self) -> f64 {
+        self.bounds.right()
+    }
+
+    fn set_right(&mut self, x: f64) {
+        self.bounds.set_right(x);
+    }
+
+    fn resize(&mut self, point: Point, edge: Edge) -> Edge {
+        self.bounds.resize(point, edge)
+    }
+}
+
+/// Objects moved, resized and styled as one. The children are in the same frame as the group,
+/// which has no transform of its own, and keep their own transforms. They also keep their
+/// layers, so that entering or ungrouping the group gives back their ids, names and flags.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "GroupData", into = "GroupData")]
+pub struct Group {
+    children: Vec<Layer>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct GroupData {
+    children: Vec<LayerData>,
+}
+
+impl TryFrom<GroupData> for Group {
+    type Error = &'static str;
+
+    fn try_from(data: GroupData) -> Result<Self, Self::Error> {
+        if data.children.is_empty() {
+            return Err("Group without children");
+        }
+        let children = data.children.into_iter().map(Layer::from);
+        Ok(Group::new(children.collect()))
+    }
+}
+
+impl From<Group> for GroupData {
+    fn from(group: Group) -> Self {
+        let children = group.children.iter().map(LayerData::from);
+        GroupData { children: children.collect() }
+    }
+}
+
+impl fmt::Debug for Group {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        GroupData::from(self.clone()).fmt(f)
+    }
+}
+
+impl Group {
+    /// Groups `children`, bottom to top. There must be at least one.
+    pub fn new(children: Vec<Layer>) -> Group {
+        assert!(!children.is_empty(), "Group without children");
+        Group { children }
+    }
+
+    pub fn children(&self) -> &[Layer] {
+        &self.children
+    }
+
+    fn objects(&self) -> impl Iterator<Item = &dyn Object> {
+        self.children.iter().map(|child| &*child.object)
+    }
+
+    /// Hidden children are neither drawn nor hit, like hidden layers.
+    fn shown_objects(&self) -> impl Iterator<Item = &dyn Object> {
+        self.children
+            .iter()
+            .filter(|child| !child.meta.hidden)
+            .map(|child| &*child.object)
+    }
+
+    fn children_bounds(&self) -> Rect {
+        self.objects()
+            .map(|child| child.world_bounds())
+            .reduce(|a, b| a.union(&b))
+            .unwrap()
+    }
+
+    /// Scales and moves the children so that their bounds become `to`.
+    fn fit(&mut self, to: &Rect) {
+        self.apply_transform(self.children_bounds().map_onto(to));
+    }
+}
+
+impl Object for Group {
+    fn draw(&self, surface: &dyn Surface) {
+        for child in self.shown_objects() {
+            transformed(surface, child, || child.draw(surface));
+        }
+    }
+
+    fn draw_outline(&self, surface: &dyn Surface, zoom: f64) {
+        self.children_bounds().draw_outline(surface, zoom);
+    }
+
+    fn draw_active(&self, surface: &dyn Surface, zoom: f64) {
+        self.draw_outline(surface, zoom);
+
+        for edge in self.edges() {
+            edge.draw(surface, zoom);
+        }
+        draw_rotate_handle(surface, self, zoom);
+    }
+
+    fn is_point_over(&self, point: Point, tolerance: f64) -> bool {
+        self.shown_objects()
+            .any(|child| child.is_point_over(point, tolerance))
+    }
+
+    fn edges(&self) -> Vec<Edge> {
+        self.children_bounds().edges()
+    }
+
+    /// Scales the children along with their bounds.
+    fn resize(&mut self, point: Point, edge: Edge) -> Edge {
+        let mut to = self.children_bounds();
+        let edge = to.resize(point, edge);
+        self.fit(&to);
+        edge
+    }
+
+    /// Only ever called with affine maps, which three points pin down.
+    fn map_points(&mut self, map: &dyn Fn(Point) -> Point) {
+        let origin = map(Point { x: 0.0, y: 0.0 });
+        let x = map(Point { x: 1.0, y: 0.0 }).subtract(origin);
+        let y = map(Point { x: 0.0, y: 1.0 }).subtract(origin);
+        self.apply_transform(Transform {
+            a: x.x,
+            b: x.y,
+            c: y.x,
+            d: y.y,
+            e: origin.x,
+            f: origin.y,
+        });
+    }
+
+    fn transform(&self) -> Transform {
+        Transform::IDENTITY
+    }
+
+    fn apply_transform(&mut self, transform: Transform) {
+        for child in &mut self.children {
+            child.object.apply_transform(transform);
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn Object> {
+        Box::new(self.clone())
+    }
+
+    fn to_data(&self) -> ObjectData {
+        ObjectData::Group(self.clone())
+    }
+
+    fn kind(&self) -> &'static str {
+        "group"
+    }
+
+    fn to_svg(&self) -> String {
+        let children: String = self.shown_objects().map(|child| child.to_svg()).collect();
+        format!("<g>{}</g>", children)
+    }
+
+    /// The style of the bottom child stands for the group.
+    fn style(&self) -> &Style {
+        self.children[0].object.style()
+    }
+
+    fn style_mut(&mut self) -> &mut Style {
+        self.children[0].object.style_mut()
+    }
+
+    fn update_style(&mut self, update: &dyn Fn(&mut Style)) -> bool {
+        let mut changed = false;
+        for child in &mut self.children {
+            changed |= child.object.update_style(update);
+        }
+        changed
+    }
+
+    fn as_group(&self) -> Option<&Group> {
+        Some(self)
+    }
+
+    fn top(&self) -> f64 {
+        self.children_bounds().top()
+    }
+
+    fn set_top(&mut self, y: f64) {
+        let bounds = self.children_bounds();
+        self.fit(&Rect::new(
+            Point { x: bounds.left(), y },
+            bounds.bottom_right(),
+        ));
+    }
+
+    fn left(&self) -> f64 {
+        self.children_bounds().left()
+    }
+
+    fn set_left(&mut self, x: f64) {
+        let bounds = self.children_bounds();
+        self.fit(&Rect::new(
+            Point { x, y: bounds.top() },
+            bounds.bottom_right(),
+        ));
+    }
+
+    fn bottom(&self) -> f64 {
+        self.children_bounds().bottom()
+    }
+
+    fn set_bottom(&mut self, y: f64) {
+        let bounds = self.children_bounds();
+        self.fit(&Rect::new(
+            bounds.top_left(),
+            Point { x: bounds.right(), y },
+        ));
+    }
+
+    fn right(&self) -> f64 {
+        self.children_bounds().right()
+    }
+
+    fn set_right(&mut self, x: f64) {
+        let bounds = self.children_bounds();
+        self.fit(&Rect::new(
+            bounds.top_left(),
+            Point { x, y: bounds.bottom() },
+        ));
+    }
+
+    fn paint_bounds(&self) -> Rect {
+        self.objects()
+            .map(|child| child.paint_bounds())
+            .reduce(|a, b| a.union(&b))
+            .unwrap()
+    }
+}
+
+fn draw_rotate_handle(surface: &dyn Surface, object: &dyn Object, zoom: f64) {
+    let handle = object.rotate_handle(zoom);
+    let radius = Edge::SIZE / zoom / 2.0;
+    surface.begin_path();
+    surface.move_to(handle.x, object.bounds().top());
+    surface.line_to(handle.x, handle.y);
+    surface.stroke(OUTLINE_COLOR, 1.0 / zoom);
+    surface.begin_path();
+    surface.ellipse(handle.x, handle.y, radius, radius);
+    surface.fill("#000000");
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum EdgeKind {
     TopLeft,
-    // Top,
+    Top,
     TopRight,
-    // Right,
+    Right,
     BottomRight,
-    // Bottom,
+    Bottom,
     BottomLeft,
-    // Left,
+    Left,
+}
+
+impl EdgeKind {
+    /// Which sides the handle drags: -1 for left/top, 1 for right/bottom, 0 for neither.
+    fn direction(&self) -> (f64, f64) {
+        match self {
+            EdgeKind::TopLeft => (-1.0, -1.0),
+            EdgeKind::Top => (0.0, -1.0),
+            EdgeKind::TopRight => (1.0, -1.0),
+            EdgeKind::Right => (1.0, 0.0),
+            EdgeKind::BottomRight => (1.0, 1.0),
+            EdgeKind::Bottom => (0.0, 1.0),
+            EdgeKind::BottomLeft => (-1.0, 1.0),
+            EdgeKind::Left => (-1.0, 0.0),
+        }
+    }
+
+    /// The handle on the other side after mirroring the box horizontally and/or vertically.
+    fn flip(&self, horizontal: bool, vertical: bool) -> EdgeKind {
+        let (mut dir_x, mut dir_y) = self.direction();
+        if horizontal {
+            dir_x = -dir_x;
+        }
+        if vertical {
+            dir_y = -dir_y;
+        }
+        match (dir_x as i8, dir_y as i8) {
+            (-1, -1) => EdgeKind::TopLeft,
+            (0, -1) => EdgeKind::Top,
+            (1, -1) => EdgeKind::TopRight,
+            (1, 0) => EdgeKind::Right,
+            (1, 1) => EdgeKind::BottomRight,
+            (0, 1) => EdgeKind::Bottom,
+            (-1, 1) => EdgeKind::BottomLeft,
+            _ => EdgeKind::Left,
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
 pub struct Edge {
-    pub rect: Rect,
+    center: Point,
     kind: EdgeKind,
 }
 
 impl Edge {
+    const SIZE: f64 = 8.0;
+
     pub fn new(point: Point, kind: EdgeKind) -> Edge {
-        let size = 8.0;
-        Edge { rect: Rect::from_center(point, size, size), kind }
+        Edge { center: point, kind }
+    }
+
+    /// The handle's square, which is `SIZE` pixels wide on screen at the given `zoom`.
+    pub fn rect(&self, zoom: f64) -> Rect {
+        Rect::from_center(self.center, Edge::SIZE / zoom, Edge::SIZE / zoom)
     }
 
-    pub fn draw(&self, context: &CanvasRenderingContext2d) {
-        self.rect.draw(context);
+    pub fn draw(&self, surface: &dyn Surface, zoom: f64) {
+        self.rect(zoom).draw(surface);
     }
 
-    pub fn is_point_over(&self, context: &CanvasRenderingContext2d, point: Point) -> bool {
-        self.rect.is_point_over(context, point)
+    pub fn is_point_over(&self, point: Point, zoom: f64) -> bool {
+        self.rect(zoom).is_point_over(point, 0.0)
     }
 
-    pub fn set_cursor(&self, canvas: &HtmlCanvasElement) {
-        canvas
-            .style()
-            .set_property("cursor", self.cursor())
-            .unwrap();
+    /// The handle this one became on `to`, a box resized by it that is flipped on the sides
+    /// where its size is negative.
+    pub fn on_resized(&self, to: &Rect) -> Edge {
+        let kind = self.kind.flip(to.width < 0.0, to.height < 0.0);
+        let mut to = to.clone();
+        to.normalize();
+        to.edges()
+            .into_iter()
+            .find(|edge| edge.kind == kind)
+            .unwrap()
     }
 
-    fn cursor(&self) -> &str {
+    pub fn cursor(&self) -> &str {
         match self.kind {
             EdgeKind::TopLeft => "nwse-resize",
-            // EdgeKind::Top => "auto",
+            EdgeKind::Top => "ns-resize",
             EdgeKind::TopRight => "nesw-resize",
-            // EdgeKind::Right => "auto",
+            EdgeKind::Right => "ew-resize",
             EdgeKind::BottomRight => "nwse-resize",
-            // EdgeKind::Bottom => "auto",
+            EdgeKind::Bottom => "ns-resize",
             EdgeKind::BottomLeft => "nesw-resize",
-            // EdgeKind::Left => "auto",
+            EdgeKind::Left => "ew-resize",
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::surface::DrawOp;
+    use crate::surface::Recorder;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn hits_rect_within_tolerance() {
+        let rect = Rect::new(Point { x: 10.0, y: 10.0 }, Point { x: 50.0, y: 30.0 });
+
+        assert!(rect.is_point_over(Point { x: 30.0, y: 20.0 }, 0.0));
+        assert!(!rect.is_point_over(Point { x: 51.0, y: 20.0 }, 0.0));
+        assert!(rect.is_point_over(Point { x: 51.0, y: 20.0 }, 1.0));
+        assert!(!rect.is_point_over(Point { x: 30.0, y: 32.0 }, 1.0));
+    }
+
+    #[test]
+    fn hits_edge_handle_around_corner() {
+        let edge = Edge::new(Point { x: 10.0, y: 10.0 }, EdgeKind::TopLeft);
+
+        assert!(edge.is_point_over(Point { x: 13.0, y: 7.0 }, 1.0));
+        assert!(!edge.is_point_over(Point { x: 15.0, y: 10.0 }, 1.0));
+        assert!(edge.is_point_over(Point { x: 15.0, y: 10.0 }, 0.5));
+    }
+
+    #[test]
+    fn hits_ellipse_inside_its_outline_only() {
+        let ellipse = Ellipse::new(Point { x: 0.0, y: 0.0 }, Point { x: 40.0, y: 20.0 });
+
+        assert!(ellipse.is_point_over(Point { x: 20.0, y: 10.0 }, 0.0));
+        assert!(ellipse.is_point_over(Point { x: 40.0, y: 10.0 }, 0.0));
+        assert!(!ellipse.is_point_over(Point { x: 2.0, y: 2.0 }, 0.0));
+        assert!(!ellipse.is_point_over(Point { x: 41.0, y: 10.0 }, 0.0));
+        assert!(ellipse.is_point_over(Point { x: 41.0, y: 10.0 }, 1.0));
+    }
+
+    #[test]
+    fn resizes_ellipse_from_corner_handle() {
+        let mut ellipse = Ellipse::new(Point { x: 0.0, y: 0.0 }, Point { x: 40.0, y: 20.0 });
+        let edge = ellipse.edges()[4];
+
+        ellipse.resize(Point { x: 60.0, y: 40.0 }, edge);
+
+        assert_eq!(ellipse.center().x, 30.0);
+        assert_eq!(ellipse.radius_y(), 20.0);
+    }
+
+    #[test]
+    fn resizes_rect_from_side_handle() {
+        let mut rect = Rect::new(Point { x: 0.0, y: 0.0 }, Point { x: 40.0, y: 20.0 });
+        let edge = rect.edges()[3];
+
+        rect.resize(Point { x: 60.0, y: 100.0 }, edge);
+
+        assert_eq!(rect.bottom_right(), Point { x: 60.0, y: 20.0 });
+        assert_eq!(edge.cursor(), "ew-resize");
+    }
+
+    #[test]
+    fn flips_handle_when_dragged_past_opposite_side() {
+        let mut rect = Rect::new(Point { x: 10.0, y: 10.0 }, Point { x: 50.0, y: 30.0 });
+        let edge = rect.edges()[4];
+
+        let edge = rect.resize(Point { x: 0.0, y: 20.0 }, edge);
+        assert_eq!(edge.kind, EdgeKind::BottomLeft);
+        assert_eq!(edge.cursor(), "nesw-resize");
+        assert_eq!(rect.top_left(), Point { x: 0.0, y: 10.0 });
+        assert_eq!(rect.bottom_right(), Point { x: 10.0, y: 20.0 });
+
+        let edge = rect.resize(Point { x: -5.0, y: 0.0 }, edge);
+        assert_eq!(edge.kind, EdgeKind::TopLeft);
+        assert_eq!(rect.top_left(), Point { x: -5.0, y: 0.0 });
+        assert_eq!(rect.bottom_right(), Point { x: 10.0, y: 10.0 });
+    }
+
+    #[test]
+    fn hits_and_bounds_rotated_rect() {
+        let mut rect = Rect::new(Point { x: 0.0, y: 0.0 }, Point { x: 40.0, y: 20.0 });
+        rect.rotate(PI / 2.0);
+
+        assert!(rect.is_point_over(Point { x: 20.0, y: 25.0 }, 0.0));
+        assert!(!rect.is_point_over(Point { x: 35.0, y: 10.0 }, 0.0));
+        let bounds = rect.world_bounds();
+        assert!((bounds.left() - 10.0).abs() < 1e-9);
+        assert!((bounds.bottom() - 30.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn resizes_rotated_rect_around_opposite_corner() {
+        let mut rect = Rect::new(Point { x: 0.0, y: 0.0 }, Point { x: 40.0, y: 20.0 });
+        rect.rotate(PI / 2.0);
+        let fixed = rect.to_world(rect.top_left());
+        let edge = rect.edges()[4];
+
+        let dragged = Point { x: 0.0, y: 60.0 };
+        rect.resize_in_world(dragged, edge);
+
+        let distance = |a: Point, b: Point| (a.x - b.x).hypot(a.y - b.y);
+        assert!(distance(rect.to_world(rect.top_left()), fixed) < 1e-9);
+        assert!(distance(rect.to_world(rect.bottom_right()), dragged) < 1e-9);
+    }
+
+    #[test]
+    fn exports_transform_as_svg_matrix() {
+        let mut ellipse = Ellipse::new(Point { x: 0.0, y: 0.0 }, Point { x: 40.0, y: 20.0 });
+        ellipse.apply_transform(Transform::scale(-1.0, 1.0).around(ellipse.center()));
+
+        assert!(ellipse
+            .to_svg()
+            .ends_with(r#" transform="matrix(-1 0 0 1 40 0)"/>"#));
+    }
+
+    #[test]
+    fn hits_skewed_rect_in_its_local_frame() {
+        let mut rect = Rect::new(Point { x: 0.0, y: 0.0 }, Point { x: 20.0, y: 20.0 });
+        rect.apply_transform(Transform::skew(PI / 4.0, 0.0));
+
+        assert!(rect.is_point_over(Point { x: 35.0, y: 18.0 }, 0.0));
+        assert!(!rect.is_point_over(Point { x: 2.0, y: 18.0 }, 0.0));
+        assert_eq!(rect.world_bounds().right(), 40.0);
+    }
+
+    #[test]
+    fn keeps_axis_aligned_transforms_in_the_geometry() {
+        let mut text = Text::new(Point { x: 0.0, y: 0.0 }, Point { x: 20.0, y: 10.0 }, "a");
+
+        text.apply_transform(Transform::scale(2.0, 3.0));
+        assert!(text.transform().is_identity());
+        assert_eq!(text.bottom_right(), Point { x: 40.0, y: 30.0 });
+
+        text.apply_transform(Transform::scale(-1.0, 1.0));
+        assert_eq!(text.transform(), Transform::scale(-1.0, 1.0));
+        assert!(text.is_point_over(Point { x: -10.0, y: 10.0 }, 0.0));
+    }
+
+    #[test]
+    fn resizes_with_locked_aspect_ratio_or_from_center() {
+        let rect = Rect::new(Point { x: 0.0, y: 0.0 }, Point { x: 40.0, y: 20.0 });
+        let (corner, side) = (rect.edges()[4], rect.edges()[5]);
+
+        let locked = rect.resized(Point { x: 50.0, y: 40.0 }, corner, true, false);
+        assert_eq!(locked.top_left(), Point { x: 0.0, y: 0.0 });
+        assert_eq!(locked.bottom_right(), Point { x: 80.0, y: 40.0 });
+
+        let centered = rect.resized(Point { x: 50.0, y: 30.0 }, corner, false, true);
+        assert_eq!(centered.top_left(), Point { x: -10.0, y: -10.0 });
+        assert_eq!(centered.bottom_right(), Point { x: 50.0, y: 30.0 });
+
+        let both = rect.resized(Point { x: 0.0, y: 30.0 }, side, true, true);
+        assert_eq!(both.top_left(), Point { x: -20.0, y: -10.0 });
+        assert_eq!(both.bottom_right(), Point { x: 60.0, y: 30.0 });
+    }
+
+    #[test]
+    fn hits_line_near_its_segment_only() {
+        let line = Line::new(Point { x: 0.0, y: 0.0 }, Point { x: 100.0, y: 0.0 });
+
+        assert!(line.is_point_over(Point { x: 50.0, y: 1.0 }, 0.0));
+        assert!(!line.is_point_over(Point { x: 50.0, y: 3.0 }, 0.0));
+        assert!(line.is_point_over(Point { x: 50.0, y: 3.0 }, 2.0));
+        assert!(!line.is_point_over(Point { x: 104.0, y: 0.0 }, 2.0));
+    }
+
+    #[test]
+    fn resizes_line_by_moving_the_grabbed_endpoint() {
+        let mut line = Line::new(Point { x: 100.0, y: 0.0 }, Point { x: 0.0, y: 50.0 });
+        let edge = line.edges()[1];
+
+        line.resize(Point { x: 10.0, y: 60.0 }, edge);
+
+        assert_eq!(line.start.x, 100.0);
+        assert_eq!(line.end.x, 10.0);
+        assert_eq!(line.end.y, 60.0);
+
+        let edge = line.resize(Point { x: 120.0, y: -10.0 }, edge);
+        assert_eq!(edge.kind, EdgeKind::TopRight);
+        line.resize(Point { x: 130.0, y: -20.0 }, edge);
+        assert_eq!(line.start, Point { x: 100.0, y: 0.0 });
+        assert_eq!(line.end, Point { x: 130.0, y: -20.0 });
+    }
+
+    #[test]
+    fn keeps_text_inside_its_box() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut text = Text::new(Point { x: 10.0, y: 10.0 }, Point { x: 50.0, y: 30.0 }, "a");
+        text.style_mut().fill = Some("black".to_string());
+        text.set_content("Much longer than forty pixels");
+
+        text.draw(&surface);
+
+        let ops = surface.ops();
+        let clip = DrawOp::Clip { x: 10.0, y: 10.0, width: 40.0, height: 20.0 };
+        assert!(ops.contains(&clip));
+        assert!(ops.iter().any(|op| matches!(
+            op,
+            DrawOp::Text { text, max_width, .. } if text == "Much longer than forty pixels" && *max_width == 40.0
+        )));
+        assert_eq!(text.paint_bounds().bottom_right(), text.bottom_right());
+    }
+
+    #[test]
+    fn escapes_text_for_svg() {
+        let text = Text::new(
+            Point { x: 0.0, y: 0.0 },
+            Point { x: 10.0, y: 10.0 },
+            "a < b & \"c\"",
+        );
+
+        assert!(text
+            .to_svg()
+            .ends_with(">a &lt; b &amp; &quot;c&quot;</text>"));
+    }
+
+    #[test]
+    fn resizes_group_children_proportionally() {
+        let mut rect = Rect::new(Point { x: 0.0, y: 0.0 }, Point { x: 10.0, y: 10.0 });
+        rect.rotate(PI / 2.0);
+        let line = Line::new(Point { x: 20.0, y: 10.0 }, Point { x: 40.0, y: 20.0 });
+        let mut group = Group::new(vec![
+            Layer::new(1, Box::new(rect)),
+            Layer::new(2, Box::new(line)),
+        ]);
+        let edge = group.edges()[4];
+
+        group.resize(Point { x: 80.0, y: 40.0 }, edge);
+
+        assert_eq!(group.bounds().bottom_right(), Point { x: 80.0, y: 40.0 });
+        let corner = group.children()[0].object.world_bounds().bottom_right();
+        assert!((corner.x - 20.0).abs() < 1e-9 && (corner.y - 20.0).abs() < 1e-9);
+        assert!(group.is_point_over(Point { x: 60.0, y: 30.0 }, 0.0));
+        assert!(!group.is_point_over(Point { x: 30.0, y: 30.0 }, 1.0));
+        assert!(group.to_svg().starts_with("<g><rect "));
+    }
+
+    #[test]
+    fn maps_objects_between_boxes() {
+        let from = Rect::new(Point { x: 0.0, y: 0.0 }, Point { x: 100.0, y: 100.0 });
+        let to = Rect::new(Point { x: 100.0, y: 0.0 }, Point { x: 300.0, y: 50.0 });
+        let mut line = Line::new(Point { x: 50.0, y: 0.0 }, Point { x: 100.0, y: 100.0 });
+
+        line.apply_transform(from.map_onto(&to));
+
+        assert_eq!((line.start.x, line.start.y), (200.0, 0.0));
+        assert_eq!((line.end.x, line.end.y), (300.0, 50.0));
+    }
+}
@@ -1,22 +1,206 @@
-use crate::state::State;
-use std::cell::RefCell;
-use std::rc::Rc;
+use crate::events::Transform;
+use crate::layers::LayerState;
+use crate::layers::Layers;
+use crate::objects::Object;
+use crate::objects::Rect;
+use crate::objects::OUTLINE_COLOR;
+use crate::surface::Surface;
 
-pub fn render(state: &Rc<RefCell<State>>) {
-    let state = state.borrow_mut();
-    let canvas = state.canvas();
-    let context = state.context();
+/// Part of the canvas that no longer matches the layers.
+#[derive(Clone, Debug, Default)]
+pub enum Damage {
+    #[default]
+    None,
+    /// A box in canvas pixels.
+    Area(Rect),
+    Everything,
+}
+
+impl Damage {
+    pub fn add(&mut self, area: &Rect) {
+        *self = match self {
+            Damage::None => Damage::Area(area.clone()),
+            Damage::Area(damaged) => Damage::Area(damaged.union(area)),
+            Damage::Everything => Damage::Everything,
+        };
+    }
+}
+
+pub fn render(surface: &dyn Surface, layers: &Layers) {
+    surface.clear();
+
+    let viewport = layers.viewport();
+    with_transform(surface, viewport.transform(), || {
+        draw_document(surface, layers, viewport.zoom(), None)
+    });
+}
+
+/// Repaints only what `damage` covers, leaving the rest of the canvas as it is.
+pub fn render_damage(surface: &dyn Surface, layers: &Layers, damage: Damage) {
+    let area = match damage {
+        Damage::None => return,
+        Damage::Area(area) => area,
+        Damage::Everything => return render(surface, layers),
+    };
+    // Whole device pixels, so that no antialiased seam is left around the area
+    let ratio = surface.pixel_ratio();
+    let down = |value: f64| (value * ratio).floor() / ratio;
+    let up = |value: f64| (value * ratio).ceil() / ratio;
+    let (left, top) = (down(area.left()), down(area.top()));
+    let (width, height) = (up(area.right()) - left, up(area.bottom()) - top);
+
+    surface.save();
+    surface.clip(left, top, width, height);
+    surface.clear_rect(left, top, width, height);
+    let viewport = layers.viewport();
+    let visible = Rect::new(
+        viewport.to_world(area.top_left()),
+        viewport.to_world(area.bottom_right()),
+    );
+    with_transform(surface, viewport.transform(), || {
+        draw_document(surface, layers, viewport.zoom(), Some(&visible))
+    });
+    surface.restore();
+}
+
+/// Draws the shown layers and everything on top of them in document coordinates. With an
+/// `area`, layers that do not reach into it are skipped as well.
+fn draw_document(surface: &dyn Surface, layers: &Layers, zoom: f64, area: Option<&Rect>) {
+    for layer in layers.drawn_layers() {
+        let object = &*layer.object;
+        if layer.meta.hidden || area.is_some_and(|area| !object.paint_bounds().intersects(area)) {
+            continue;
+        }
+        transformed(surface, object, || object.draw(surface));
+    }
+    if let Some(bounds) = layers.entered_bounds() {
+        surface.begin_path();
+        surface.rect(
+            bounds.left(),
+            bounds.top(),
+            bounds.right() - bounds.left(),
+            bounds.bottom() - bounds.top(),
+        );
+        surface.stroke(OUTLINE_COLOR, 1.0 / zoom);
+    }
+    if let Some(outlined_layer) = layers.outlined_layer() {
+        let object = &*layers.layers().layer(*outlined_layer).object;
+        transformed(surface, object, || object.draw_outline(surface, zoom));
+    }
+    match layers.selection() {
+        [] => {}
+        [layer] => {
+            let object = &*layers.layers().layer(*layer).object;
+            transformed(surface, object, || object.draw_active(surface, zoom));
+        }
+        selection => {
+            for layer in selection {
+                let object = &*layers.layers().layer(*layer).object;
+                transformed(surface, object, || object.draw_outline(surface, zoom));
+            }
+            let bounds = layers.selection_bounds().unwrap();
+            surface.begin_path();
+            surface.rect(
+                bounds.left(),
+                bounds.top(),
+                bounds.right() - bounds.left(),
+                bounds.bottom() - bounds.top(),
+            );
+            surface.stroke(OUTLINE_COLOR, 1.0 / zoom);
+            for edge in layers.selection_edges() {
+                edge.draw(surface, zoom);
+            }
+        }
+    }
+    if let Some(LayerState::Marquee { start, end, .. }) = layers.state() {
+        surface.begin_path();
+        surface.rect(start.x, start.y, end.x - start.x, end.y - start.y);
+        surface.stroke(OUTLINE_COLOR, 1.0 / zoom);
+    }
+}
 
-    context.begin_path();
-    context.clear_rect(0.0, 0.0, canvas.width().into(), canvas.height().into());
+/// Runs `draw` with the surface mapping the object's local frame onto the document.
+pub fn transformed(surface: &dyn Surface, object: &dyn Object, draw: impl FnOnce()) {
+    with_transform(surface, object.transform(), draw)
+}
 
-    for layer in &state.layers {
-        layer.object.draw(&context);
+fn with_transform(surface: &dyn Surface, transform: Transform, draw: impl FnOnce()) {
+    if transform.is_identity() {
+        return draw();
     }
-    if let Some(outlined_layer) = state.outlined_layer {
-        state.layers[outlined_layer].object.draw_outline(&context);
+    let Transform { a, b, c, d, e, f } = transform;
+    surface.save();
+    surface.transform(a, b, c, d, e, f);
+    draw();
+    surface.restore();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::Point;
+    use crate::layers::Layer;
+    use crate::surface::DrawOp;
+    use crate::surface::Recorder;
+
+    #[test]
+    fn repaints_only_layers_in_damaged_area() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = Layers::new();
+        let rect = |x: f64| -> Box<dyn Object> {
+            Box::new(Rect::new(
+                Point { x, y: 10.0 },
+                Point { x: x + 40.0, y: 30.0 },
+            ))
+        };
+        layers.load(vec![Layer::new(1, rect(10.0)), Layer::new(2, rect(100.0))]);
+        render(&surface, &layers);
+
+        let area = Rect::new(Point { x: 0.5, y: 0.0 }, Point { x: 60.0, y: 39.5 });
+        render_damage(&surface, &layers, Damage::Area(area));
+
+        let ops = surface.ops();
+        let clip = ops
+            .iter()
+            .position(|op| op == &DrawOp::Clip { x: 0.0, y: 0.0, width: 60.0, height: 40.0 });
+        let drawn = |x: f64| {
+            ops.iter()
+                .rposition(|op| op == &DrawOp::Rect { x, y: 10.0, width: 40.0, height: 20.0 })
+        };
+        assert!(drawn(10.0) > clip);
+        assert!(drawn(100.0) < clip);
     }
-    if let Some(active_layer) = state.active_layer {
-        state.layers[active_layer].object.draw_active(&context);
+
+    #[test]
+    fn clips_damage_to_whole_device_pixels() {
+        let surface = Recorder::new(800.0, 400.0).with_pixel_ratio(1.5);
+        let layers = Layers::new();
+
+        let area = Rect::new(Point { x: 10.2, y: 0.5 }, Point { x: 20.5, y: 2.0 });
+        render_damage(&surface, &layers, Damage::Area(area));
+
+        // From device pixel 15 to 31 across and 0 to 3 down
+        assert!(surface.ops().contains(&DrawOp::Clip {
+            x: 10.0,
+            y: 0.0,
+            width: 31.0 / 1.5 - 10.0,
+            height: 2.0,
+        }));
+    }
+
+    #[test]
+    fn leaves_hidden_layers_out() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = Layers::new();
+        let rect = Rect::new(Point { x: 10.0, y: 10.0 }, Point { x: 50.0, y: 30.0 });
+        layers.load(vec![Layer::new(1, Box::new(rect))]);
+        layers.set_layer_meta(1, |meta| meta.hidden = true);
+
+        render(&surface, &layers);
+
+        assert!(!surface
+            .ops()
+            .iter()
+            .any(|op| matches!(op, DrawOp::Rect { .. })));
     }
 }
@@ -0,0 +1,90 @@
+use crate::events::Modifiers;
+use crate::events::Point;
+use crate::layers::Reorder;
+
+/// Editor commands bound to the keyboard.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shortcut {
+    Undo,
+    Redo,
+    /// Removes the selected layers.
+    Delete,
+    /// Copies the selected layers next to them.
+    Duplicate,
+    /// Moves the selected layers by `offset`.
+    Nudge(Point),
+    /// Aborts the gesture in progress, or clears the selection when there is none.
+    Cancel,
+    SelectAll,
+    Group,
+    /// Replaces the selected groups with their children.
+    Ungroup,
+    /// Moves the selected layers up or down the stack.
+    Reorder(Reorder),
+    FlipHorizontal,
+    FlipVertical,
+    /// While held, mouse drags scroll the view instead of editing.
+    Pan,
+}
+
+impl Shortcut {
+    pub fn from_key(key: &str, modifiers: Modifiers) -> Option<Shortcut> {
+        // Cmd on macOS plays the role of Ctrl elsewhere
+        let command = modifiers.ctrl || modifiers.meta;
+        let step = if modifiers.shift { 10.0 } else { 1.0 };
+
+        let shortcut = match key {
+            "z" | "Z" if command && modifiers.shift => Shortcut::Redo,
+            "z" | "Z" if command => Shortcut::Undo,
+            "y" | "Y" if command => Shortcut::Redo,
+            "a" | "A" if command => Shortcut::SelectAll,
+            "d" | "D" if command => Shortcut::Duplicate,
+            "g" | "G" if command && modifiers.shift => Shortcut::Ungroup,
+            "g" | "G" if command => Shortcut::Group,
+            // Shift turns the brackets into braces on most layouts
+            "]" | "}" if command && modifiers.shift => Shortcut::Reorder(Reorder::ToFront),
+            "]" if command => Shortcut::Reorder(Reorder::Forward),
+            "[" | "{" if command && modifiers.shift => Shortcut::Reorder(Reorder::ToBack),
+            "[" if command => Shortcut::Reorder(Reorder::Backward),
+            "H" if modifiers.shift && !command => Shortcut::FlipHorizontal,
+            "V" if modifiers.shift && !command => Shortcut::FlipVertical,
+            "Delete" | "Backspace" => Shortcut::Delete,
+            "Escape" => Shortcut::Cancel,
+            " " if !command => Shortcut::Pan,
+            "ArrowLeft" => Shortcut::Nudge(Point { x: -step, y: 0.0 }),
+            "ArrowRight" => Shortcut::Nudge(Point { x: step, y: 0.0 }),
+            "ArrowUp" => Shortcut::Nudge(Point { x: 0.0, y: -step }),
+            "ArrowDown" => Shortcut::Nudge(Point { x: 0.0, y: step }),
+            _ => return None,
+        };
+        Some(shortcut)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_keys_with_modifiers() {
+        let none = Modifiers::default();
+        let ctrl = Modifiers { ctrl: true, ..none };
+        let meta_shift = Modifiers { meta: true, shift: true, ..none };
+
+        assert_eq!(Shortcut::from_key("z", ctrl), Some(Shortcut::Undo));
+        assert_eq!(Shortcut::from_key("Z", meta_shift), Some(Shortcut::Redo));
+        assert_eq!(Shortcut::from_key("a", none), None);
+        assert_eq!(
+            Shortcut::from_key("}", Modifiers { ctrl: true, shift: true, ..none }),
+            Some(Shortcut::Reorder(Reorder::ToFront))
+        );
+        assert_eq!(
+            Shortcut::from_key("[", ctrl),
+            Some(Shortcut::Reorder(Reorder::Backward))
+        );
+        assert_eq!(
+            Shortcut::from_key("ArrowUp", Modifiers { shift: true, ..none }),
+            Some(Shortcut::Nudge(Point { x: 0.0, y: -10.0 }))
+        );
+    }
+}
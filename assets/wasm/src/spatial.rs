@@ -0,0 +1,170 @@
+use crate::layers::LayerId;
+use crate::objects::Object;
+use crate::objects::Rect;
+use std::collections::HashMap;
+
+/// Side of a grid cell, in document units.
+const CELL_SIZE: f64 = 128.0;
+/// Layers covering more cells than this go in a list that every query checks, so that one huge
+/// shape does not fill the grid.
+const MAX_CELLS: i64 = 256;
+
+/// Cells a layer is filed under, as inclusive `(left, top, right, bottom)` cell coordinates.
+#[derive(Clone, Copy)]
+struct CellRange(i64, i64, i64, i64);
+
+impl CellRange {
+    fn of(area: &Rect) -> CellRange {
+        let cell = |value: f64| (value / CELL_SIZE).floor() as i64;
+        CellRange(
+            cell(area.left()),
+            cell(area.top()),
+            cell(area.right()),
+            cell(area.bottom()),
+        )
+    }
+
+    fn count(&self) -> i64 {
+        (self.2 - self.0 + 1).saturating_mul(self.3 - self.1 + 1)
+    }
+
+    fn contains(&self, (x, y): (i64, i64)) -> bool {
+        self.0 <= x && x <= self.2 && self.1 <= y && y <= self.3
+    }
+
+    fn cells(&self) -> impl Iterator<Item = (i64, i64)> {
+        let CellRange(left, top, right, bottom) = *self;
+        (top..=bottom).flat_map(move |y| (left..=right).map(move |x| (x, y)))
+    }
+}
+
+/// Uniform grid over the document for finding the layers near a point or area without looking
+/// at all of them. Layers are filed by id, so adding, removing or reordering some of them leaves
+/// the rest where they are.
+pub struct SpatialIndex {
+    cells: HashMap<(i64, i64), Vec<LayerId>>,
+    large: Vec<LayerId>,
+    /// Where each layer is filed; `None` for the ones in `large`.
+    ranges: HashMap<LayerId, Option<CellRange>>,
+}
+
+impl SpatialIndex {
+    pub fn new() -> Self {
+        SpatialIndex { cells: HashMap::new(), large: Vec::new(), ranges: HashMap::new() }
+    }
+
+    /// Files every layer anew from its bounds, e.g. after a new document was loaded.
+    pub fn rebuild(&mut self, bounds: impl Iterator<Item = (LayerId, Rect)>) {
+        self.cells.clear();
+        self.large.clear();
+        self.ranges.clear();
+        for (layer, bounds) in bounds {
+            self.insert(layer, &bounds);
+        }
+    }
+
+    /// Files `layer` under `bounds`, replacing where it was filed before.
+    pub fn insert(&mut self, layer: LayerId, bounds: &Rect) {
+        self.remove(layer);
+        let range = CellRange::of(bounds);
+        if range.count() > MAX_CELLS {
+            self.large.push(layer);
+            self.ranges.insert(layer, None);
+            return;
+        }
+        for cell in range.cells() {
+            self.cells.entry(cell).or_default().push(layer);
+        }
+        self.ranges.insert(layer, Some(range));
+    }
+
+    pub fn remove(&mut self, layer: LayerId) {
+        match self.ranges.remove(&layer) {
+            Some(Some(range)) => {
+                for cell in range.cells() {
+                    if let Some(layers) = self.cells.get_mut(&cell) {
+                        layers.retain(|&filed| filed != layer);
+                        if layers.is_empty() {
+                            self.cells.remove(&cell);
+                        }
+                    }
+                }
+            }
+            Some(None) => self.large.retain(|&filed| filed != layer),
+            None => {}
+        }
+    }
+
+    /// Layers whose bounds may intersect `area`, by id. Callers still need to check the actual
+    /// shapes.
+    pub fn query(&self, area: &Rect) -> Vec<LayerId> {
+        let range = CellRange::of(area);
+        let mut layers = self.large.clone();
+        // A large area covers more cells than are in use, so look at those instead
+        if range.count() > self.cells.len() as i64 {
+            for (cell, filed) in &self.cells {
+                if range.contains(*cell) {
+                    layers.extend(filed);
+                }
+            }
+        } else {
+            for cell in range.cells() {
+                if let Some(filed) = self.cells.get(&cell) {
+                    layers.extend(filed);
+                }
+            }
+        }
+        layers.sort_unstable();
+        layers.dedup();
+        layers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::Point;
+
+    fn rect(x: f64, y: f64, width: f64, height: f64) -> Rect {
+        Rect::new(Point { x, y }, Point { x: x + width, y: y + height })
+    }
+
+    #[test]
+    fn finds_layers_in_overlapping_cells_only() {
+        let mut index = SpatialIndex::new();
+        index.rebuild(
+            [
+                (0, rect(0.0, 0.0, 10.0, 10.0)),
+                (1, rect(500.0, 500.0, 10.0, 10.0)),
+                (2, rect(200.0, 0.0, 300.0, 10.0)),
+            ]
+            .into_iter(),
+        );
+
+        assert_eq!(index.query(&rect(5.0, 5.0, 1.0, 1.0)), vec![0]);
+        assert_eq!(index.query(&rect(300.0, 5.0, 1.0, 1.0)), vec![2]);
+        assert_eq!(index.query(&rect(0.0, 0.0, 1000.0, 1000.0)), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn moves_layers_when_reinserted() {
+        let mut index = SpatialIndex::new();
+        index.insert(0, &rect(0.0, 0.0, 10.0, 10.0));
+
+        index.insert(0, &rect(1000.0, 0.0, 10.0, 10.0));
+
+        assert!(index.query(&rect(5.0, 5.0, 1.0, 1.0)).is_empty());
+        assert_eq!(index.query(&rect(1005.0, 5.0, 1.0, 1.0)), vec![0]);
+        index.remove(0);
+        assert!(index.query(&rect(1005.0, 5.0, 1.0, 1.0)).is_empty());
+    }
+
+    #[test]
+    fn keeps_huge_layers_out_of_the_grid() {
+        let mut index = SpatialIndex::new();
+        index.insert(0, &rect(-1e6, -1e6, 2e6, 2e6));
+
+        assert!(index.cells.is_empty());
+        assert_eq!(index.query(&rect(5.0, 5.0, 1.0, 1.0)), vec![0]);
+    }
+}
@@ -1,24 +1,49 @@
+use crate::canvas::AutoSize;
 use crate::canvas::Canvas;
+use crate::canvas::RenderLoop;
 use crate::events::Point;
 use crate::layers::Layers;
 use crate::objects::Edge;
+use crate::renderer::render_damage;
+use crate::surface::Surface;
+use js_sys::Array;
+use js_sys::Function;
 use std::cell::RefCell;
 use std::rc::Rc;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use web_sys::window;
+use web_sys::ClipboardEvent;
 use web_sys::HtmlCanvasElement;
+use web_sys::KeyboardEvent;
+use web_sys::MediaQueryList;
 use web_sys::MouseEvent;
+use web_sys::ResizeObserver;
+use web_sys::WheelEvent;
 
 pub struct State {
     old_canvas: HtmlCanvasElement,
     pub canvas: Rc<RefCell<Canvas>>,
     pub layers: Rc<RefCell<Layers>>,
+    /// Called after a frame in which the layers changed, for a layer panel to update.
+    pub on_layers_change: Rc<RefCell<Option<Function>>>,
+    pub render_loop: Option<Rc<RenderLoop>>,
     pub active_layer: Option<usize>,
     pub active_edge: Option<Edge>,
     pub mouse_start: Option<Point>,
     pub outlined_layer: Option<usize>,
     pub _closuers: Vec<Closure<dyn FnMut(MouseEvent)>>,
+    pub _key_closures: Vec<Closure<dyn FnMut(KeyboardEvent)>>,
+    pub _blur_closure: Option<Closure<dyn FnMut()>>,
+    pub _wheel_closure: Option<Closure<dyn FnMut(WheelEvent)>>,
+    pub auto_size: AutoSize,
+    /// Watches the element around the canvas for `auto_size`.
+    pub resize_observer: Option<ResizeObserver>,
+    pub _resize_closure: Option<Closure<dyn FnMut(Array)>>,
+    /// Matches while the display keeps the `devicePixelRatio` the canvas was last sized for.
+    pub pixel_ratio_query: Option<MediaQueryList>,
+    pub _pixel_ratio_closure: Option<Closure<dyn FnMut()>>,
+    pub _clipboard_closures: Vec<Closure<dyn FnMut(ClipboardEvent)>>,
 }
 
 impl State {
@@ -31,11 +56,22 @@ impl State {
             canvas,
             old_canvas,
             layers,
+            on_layers_change: Rc::new(RefCell::new(None)),
+            render_loop: None,
             active_layer: None,
             active_edge: None,
             outlined_layer: None,
             mouse_start: None,
             _closuers: Vec::new(),
+            _key_closures: Vec::new(),
+            _blur_closure: None,
+            _wheel_closure: None,
+            auto_size: AutoSize::new(),
+            resize_observer: None,
+            _resize_closure: None,
+            pixel_ratio_query: None,
+            _pixel_ratio_closure: None,
+            _clipboard_closures: Vec::new(),
         })
     }
 
@@ -43,6 +79,26 @@ impl State {
         &self.old_canvas
     }
 
+    /// Draws what changed in the next animation frame.
+    pub fn schedule_render(&self) {
+        if let Some(render_loop) = &self.render_loop {
+            render_loop.schedule();
+        }
+    }
+
+    /// Resizes the canvas to `width` by `height` CSS pixels and redraws the document on it.
+    pub fn resize(&self, width: f64, height: f64) -> Result<(), JsValue> {
+        let mut canvas = self.canvas.borrow_mut();
+        let (old_width, old_height) = (canvas.width(), canvas.height());
+        canvas.set_size(width, height)?;
+        let mut layers = self.layers.borrow_mut();
+        layers.on_resize(old_width, old_height, &*canvas);
+        // Draw right away instead of leaving the canvas blank until the next frame
+        let damage = layers.take_damage();
+        render_damage(&*canvas, &layers, damage);
+        Ok(())
+    }
+
     fn init_canvas(canvas_id: &str) -> Result<HtmlCanvasElement, JsValue> {
         let canvas = window()
             .ok_or("Window not found")?
@@ -56,7 +112,7 @@ impl State {
     }
 }
 
-impl From<State> for Rc<RefCell<State>>  {
+impl From<State> for Rc<RefCell<State>> {
     fn from(state: State) -> Self {
         Rc::new(RefCell::new(state))
     }
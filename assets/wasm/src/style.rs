@@ -0,0 +1,94 @@
+use crate::surface::Surface;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// How an object paints its path. Colors are any CSS color; `None` means no fill or stroke.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Style {
+    pub fill: Option<String>,
+    pub stroke: Option<String>,
+    pub stroke_width: f64,
+    pub opacity: f64,
+    pub dash: Vec<f64>,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            fill: Some("#000000".to_string()),
+            stroke: None,
+            stroke_width: 1.0,
+            opacity: 1.0,
+            dash: Vec::new(),
+        }
+    }
+}
+
+impl Style {
+    /// Default style for open paths, which have nothing to fill.
+    pub fn line() -> Self {
+        Style {
+            fill: None,
+            stroke: Some("#000000".to_string()),
+            stroke_width: 2.0,
+            ..Style::default()
+        }
+    }
+
+    /// Fills and strokes the surface's current path.
+    pub fn paint(&self, surface: &dyn Surface) {
+        surface.set_alpha(self.opacity);
+        if let Some(fill) = &self.fill {
+            surface.fill(fill);
+        }
+        if let Some(stroke) = &self.stroke {
+            surface.set_line_dash(&self.dash);
+            surface.stroke(stroke, self.stroke_width);
+            surface.set_line_dash(&[]);
+        }
+        surface.set_alpha(1.0);
+    }
+
+    /// Stroke width that sticks out of the object's geometry on each side.
+    pub fn stroke_overhang(&self) -> f64 {
+        match self.stroke {
+            Some(_) => self.stroke_width / 2.0,
+            None => 0.0,
+        }
+    }
+
+    pub fn to_svg_attributes(&self) -> String {
+        let mut attributes = format!(r#"fill="{}""#, self.fill.as_deref().unwrap_or("none"));
+        if let Some(stroke) = &self.stroke {
+            attributes.push_str(&format!(
+                r#" stroke="{}" stroke-width="{}""#,
+                stroke, self.stroke_width
+            ));
+            if !self.dash.is_empty() {
+                let dash: Vec<String> = self.dash.iter().map(f64::to_string).collect();
+                attributes.push_str(&format!(r#" stroke-dasharray="{}""#, dash.join(" ")));
+            }
+        }
+        if self.opacity < 1.0 {
+            attributes.push_str(&format!(r#" opacity="{}""#, self.opacity));
+        }
+        attributes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_svg_attributes() {
+        assert_eq!(Style::default().to_svg_attributes(), r##"fill="#000000""##);
+
+        let style = Style { dash: vec![4.0, 2.0], opacity: 0.5, ..Style::line() };
+        assert_eq!(
+            style.to_svg_attributes(),
+            r##"fill="none" stroke="#000000" stroke-width="2" stroke-dasharray="4 2" opacity="0.5""##
+        );
+    }
+}
@@ -0,0 +1,240 @@
+use std::cell::RefCell;
+
+/// A 2d drawing target. `Canvas` draws to the browser, `Recorder` keeps the
+/// calls in memory so everything above it can run without a DOM.
+pub trait Surface {
+    fn width(&self) -> f64;
+    fn height(&self) -> f64;
+    /// Device pixels per unit of `width` and `height`.
+    fn pixel_ratio(&self) -> f64;
+    fn clear(&self);
+    fn clear_rect(&self, x: f64, y: f64, width: f64, height: f64);
+    /// Limits drawing to the rectangle, in the current coordinates, until the matching
+    /// `restore`.
+    fn clip(&self, x: f64, y: f64, width: f64, height: f64);
+    fn begin_path(&self);
+    fn rect(&self, x: f64, y: f64, width: f64, height: f64);
+    fn ellipse(&self, x: f64, y: f64, radius_x: f64, radius_y: f64);
+    fn move_to(&self, x: f64, y: f64);
+    fn line_to(&self, x: f64, y: f64);
+    fn set_alpha(&self, alpha: f64);
+    fn set_line_dash(&self, dash: &[f64]);
+    fn fill(&self, color: &str);
+    fn stroke(&self, color: &str, line_width: f64);
+    /// Draws one line of `text` with its top left at `x`, `y`, squeezed to be no wider than
+    /// `max_width`.
+    fn fill_text(&self, text: &str, x: f64, y: f64, font_size: f64, max_width: f64, color: &str);
+    fn set_cursor(&self, cursor: &str);
+    /// Pushes the current transform so that `restore` can go back to it.
+    fn save(&self);
+    fn restore(&self);
+    /// Multiplies the current transform by the affine matrix `[a c e; b d f]`.
+    fn transform(&self, a: f64, b: f64, c: f64, d: f64, e: f64, f: f64);
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DrawOp {
+    Clear,
+    ClearRect {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    },
+    Clip {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    },
+    Rect {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    },
+    Ellipse {
+        x: f64,
+        y: f64,
+        radius_x: f64,
+        radius_y: f64,
+    },
+    MoveTo {
+        x: f64,
+        y: f64,
+    },
+    LineTo {
+        x: f64,
+        y: f64,
+    },
+    Alpha {
+        alpha: f64,
+    },
+    LineDash {
+        dash: Vec<f64>,
+    },
+    Fill {
+        color: String,
+    },
+    Stroke {
+        color: String,
+        line_width: f64,
+    },
+    Text {
+        text: String,
+        x: f64,
+        y: f64,
+        font_size: f64,
+        max_width: f64,
+        color: String,
+    },
+    Save,
+    Restore,
+    Transform {
+        a: f64,
+        b: f64,
+        c: f64,
+        d: f64,
+        e: f64,
+        f: f64,
+    },
+}
+
+/// In-memory surface for native builds and tests.
+pub struct Recorder {
+    width: f64,
+    height: f64,
+    pixel_ratio: f64,
+    ops: RefCell<Vec<DrawOp>>,
+    cursor: RefCell<String>,
+}
+
+impl Recorder {
+    pub fn new(width: f64, height: f64) -> Self {
+        Recorder {
+            width,
+            height,
+            pixel_ratio: 1.0,
+            ops: RefCell::new(Vec::new()),
+            cursor: RefCell::new("auto".to_string()),
+        }
+    }
+
+    pub fn with_pixel_ratio(self, pixel_ratio: f64) -> Self {
+        Recorder { pixel_ratio, ..self }
+    }
+
+    /// Draw calls since the last `clear` of the whole surface.
+    pub fn ops(&self) -> Vec<DrawOp> {
+        self.ops.borrow().clone()
+    }
+
+    pub fn cursor(&self) -> String {
+        self.cursor.borrow().clone()
+    }
+}
+
+impl Surface for Recorder {
+    fn width(&self) -> f64 {
+        self.width
+    }
+
+    fn height(&self) -> f64 {
+        self.height
+    }
+
+    fn pixel_ratio(&self) -> f64 {
+        self.pixel_ratio
+    }
+
+    fn clear(&self) {
+        let mut ops = self.ops.borrow_mut();
+        ops.clear();
+        ops.push(DrawOp::Clear);
+    }
+
+    fn clear_rect(&self, x: f64, y: f64, width: f64, height: f64) {
+        self.ops
+            .borrow_mut()
+            .push(DrawOp::ClearRect { x, y, width, height });
+    }
+
+    fn clip(&self, x: f64, y: f64, width: f64, height: f64) {
+        self.ops
+            .borrow_mut()
+            .push(DrawOp::Clip { x, y, width, height });
+    }
+
+    fn begin_path(&self) {}
+
+    fn rect(&self, x: f64, y: f64, width: f64, height: f64) {
+        self.ops
+            .borrow_mut()
+            .push(DrawOp::Rect { x, y, width, height });
+    }
+
+    fn ellipse(&self, x: f64, y: f64, radius_x: f64, radius_y: f64) {
+        self.ops
+            .borrow_mut()
+            .push(DrawOp::Ellipse { x, y, radius_x, radius_y });
+    }
+
+    fn move_to(&self, x: f64, y: f64) {
+        self.ops.borrow_mut().push(DrawOp::MoveTo { x, y });
+    }
+
+    fn line_to(&self, x: f64, y: f64) {
+        self.ops.borrow_mut().push(DrawOp::LineTo { x, y });
+    }
+
+    fn set_alpha(&self, alpha: f64) {
+        self.ops.borrow_mut().push(DrawOp::Alpha { alpha });
+    }
+
+    fn set_line_dash(&self, dash: &[f64]) {
+        self.ops
+            .borrow_mut()
+            .push(DrawOp::LineDash { dash: dash.to_vec() });
+    }
+
+    fn fill(&self, color: &str) {
+        self.ops
+            .borrow_mut()
+            .push(DrawOp::Fill { color: color.to_string() });
+    }
+
+    fn stroke(&self, color: &str, line_width: f64) {
+        self.ops
+            .borrow_mut()
+            .push(DrawOp::Stroke { color: color.to_string(), line_width });
+    }
+
+    fn fill_text(&self, text: &str, x: f64, y: f64, font_size: f64, max_width: f64, color: &str) {
+        self.ops.borrow_mut().push(DrawOp::Text {
+            text: text.to_string(),
+            x,
+            y,
+            font_size,
+            max_width,
+            color: color.to_string(),
+        });
+    }
+
+    fn set_cursor(&self, cursor: &str) {
+        *self.cursor.borrow_mut() = cursor.to_string();
+    }
+
+    fn save(&self) {
+        self.ops.borrow_mut().push(DrawOp::Save);
+    }
+
+    fn restore(&self) {
+        self.ops.borrow_mut().push(DrawOp::Restore);
+    }
+
+    fn transform(&self, a: f64, b: f64, c: f64, d: f64, e: f64, f: f64) {
+        self.ops
+            .borrow_mut()
+            .push(DrawOp::Transform { a, b, c, d, e, f });
+    }
+}
@@ -0,0 +1,163 @@
+use crate::layers::Layers;
+use crate::objects::Object;
+
+/// Renders the layer stack as a standalone SVG document. Layers are written bottom to top,
+/// which SVG paints in the same order. Hidden layers are left out, as on the canvas. The
+/// document is sized to everything drawn in it, wherever the canvas is scrolled to.
+pub fn export(layers: &Layers) -> String {
+    let shown: Vec<_> = layers
+        .document()
+        .iter()
+        .filter(|layer| !layer.meta.hidden)
+        .collect();
+    let bounds = shown
+        .iter()
+        .map(|layer| layer.object.paint_bounds())
+        .reduce(|a, b| a.union(&b));
+    let (left, top, width, height) = match bounds {
+        Some(bounds) => (
+            bounds.left(),
+            bounds.top(),
+            bounds.right() - bounds.left(),
+            bounds.bottom() - bounds.top(),
+        ),
+        None => (0.0, 0.0, 0.0, 0.0),
+    };
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{2}" height="{3}" viewBox="{0} {1} {2} {3}">"#,
+        left, top, width, height
+    );
+    for layer in shown {
+        svg.push_str(&layer.object.to_svg());
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::ObjectKind;
+    use crate::surface::Recorder;
+    use crate::testing::draw;
+
+    #[test]
+    fn exports_empty_document() {
+        assert_eq!(
+            export(&Layers::new()),
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="0" height="0" viewBox="0 0 0 0"></svg>"#
+        );
+    }
+
+    #[test]
+    fn exports_one_element_per_layer_in_z_order() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = Layers::new();
+        draw(
+            &mut layers,
+            &surface,
+            ObjectKind::Ellipse,
+            (0.0, 0.0),
+            (20.0, 10.0),
+        );
+        draw(
+            &mut layers,
+            &surface,
+            ObjectKind::Rect,
+            (30.0, 30.0),
+            (10.0, 20.0),
+        );
+        draw(
+            &mut layers,
+            &surface,
+            ObjectKind::Line,
+            (0.0, 0.0),
+            (5.0, 5.0),
+        );
+
+        let svg = export(&layers);
+
+        let ellipse = svg
+            .find(r##"<ellipse cx="10" cy="5" rx="10" ry="5" fill="#000000"/>"##)
+            .unwrap();
+        let rect = svg
+            .find(r##"<rect x="10" y="20" width="20" height="10" fill="#000000"/>"##)
+            .unwrap();
+        let line = svg.find(r#"<line x1="0" y1="0" x2="5" y2="5""#).unwrap();
+        assert!(ellipse < rect && rect < line);
+    }
+
+    #[test]
+    fn sizes_document_to_the_layers() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = Layers::new();
+        draw(
+            &mut layers,
+            &surface,
+            ObjectKind::Rect,
+            (-100.0, 10.0),
+            (-50.0, 30.0),
+        );
+        draw(
+            &mut layers,
+            &surface,
+            ObjectKind::Rect,
+            (900.0, 500.0),
+            (1000.0, 600.0),
+        );
+        // Scrolled away, the canvas shows none of them
+        layers.on_resize(800.0, 400.0, &Recorder::new(10.0, 10.0));
+
+        let svg = export(&layers);
+
+        assert!(svg.starts_with(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="1102" height="592" viewBox="-101 9 1102 592">"#
+        ));
+    }
+
+    #[test]
+    fn leaves_hidden_layers_out() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = Layers::new();
+        draw(
+            &mut layers,
+            &surface,
+            ObjectKind::Rect,
+            (-100.0, 10.0),
+            (-50.0, 30.0),
+        );
+        draw(
+            &mut layers,
+            &surface,
+            ObjectKind::Rect,
+            (900.0, 500.0),
+            (1000.0, 600.0),
+        );
+        layers.set_layer_meta(2, |meta| meta.hidden = true);
+
+        let svg = export(&layers);
+
+        assert!(svg.starts_with(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="52" height="22" viewBox="-101 9 52 22">"#
+        ));
+        assert_eq!(svg.matches("<rect ").count(), 1);
+    }
+
+    #[test]
+    fn exports_text_sized_to_its_box() {
+        let surface = Recorder::new(800.0, 400.0);
+        let mut layers = Layers::new();
+        draw(
+            &mut layers,
+            &surface,
+            ObjectKind::Text,
+            (0.0, 0.0),
+            (20.0, 10.0),
+        );
+
+        let svg = export(&layers);
+
+        assert!(svg.contains(r#"font-size="10""#));
+        assert!(svg.contains(">Text</text>"));
+    }
+}
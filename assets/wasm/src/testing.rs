@@ -0,0 +1,107 @@
+//! Fixtures shared by the test modules, which drive `Layers` the way the browser events do.
+
+use crate::events::Event;
+use crate::events::Modifiers;
+use crate::events::Point;
+use crate::layers::Layers;
+use crate::objects::ObjectKind;
+use crate::surface::Recorder;
+use crate::tools::Tool;
+
+fn point((x, y): (f64, f64)) -> Point {
+    Point { x, y }
+}
+
+/// Presses at `from`, moves to `to` and releases there, without modifiers.
+pub fn drag(layers: &mut Layers, surface: &Recorder, from: (f64, f64), to: (f64, f64)) {
+    drag_with(layers, surface, from, to, Modifiers::default());
+}
+
+/// Presses at `from`, moves to `to` and releases there, holding `modifiers` all along.
+pub fn drag_with(
+    layers: &mut Layers,
+    surface: &Recorder,
+    from: (f64, f64),
+    to: (f64, f64),
+    modifiers: Modifiers,
+) {
+    layers.on_event(Event::MouseDown { point: point(from), modifiers }, surface);
+    layers.on_event(Event::MouseMove { point: point(to), modifiers }, surface);
+    layers.on_event(Event::MouseUp { point: point(to), modifiers }, surface);
+}
+
+/// Presses at `at`, for gestures that go on with `hover` and end with `release`.
+pub fn press(layers: &mut Layers, surface: &Recorder, at: (f64, f64)) {
+    let modifiers = Modifiers::default();
+    layers.on_event(Event::MouseDown { point: point(at), modifiers }, surface);
+}
+
+/// Moves the pointer to `at`, dragging if a button is down.
+pub fn hover(layers: &mut Layers, surface: &Recorder, at: (f64, f64)) {
+    hover_with(layers, surface, at, Modifiers::default());
+}
+
+pub fn hover_with(layers: &mut Layers, surface: &Recorder, at: (f64, f64), modifiers: Modifiers) {
+    layers.on_event(Event::MouseMove { point: point(at), modifiers }, surface);
+}
+
+pub fn release(layers: &mut Layers, surface: &Recorder, at: (f64, f64)) {
+    let modifiers = Modifiers::default();
+    layers.on_event(Event::MouseUp { point: point(at), modifiers }, surface);
+}
+
+pub fn double_click(layers: &mut Layers, surface: &Recorder, at: (f64, f64)) {
+    let modifiers = Modifiers::default();
+    layers.on_event(Event::DoubleClick { point: point(at), modifiers }, surface);
+}
+
+/// Creates an object of `kind` by dragging from `a` to `b`, leaving its tool active.
+pub fn draw(
+    layers: &mut Layers,
+    surface: &Recorder,
+    kind: ObjectKind,
+    a: (f64, f64),
+    b: (f64, f64),
+) {
+    layers.set_tool(Tool::Create(kind));
+    drag(layers, surface, a, b);
+}
+
+pub fn shift_click(layers: &mut Layers, surface: &Recorder, x: f64, y: f64) {
+    let point = Point { x, y };
+    let modifiers = Modifiers { shift: true, ..Modifiers::default() };
+    layers.on_event(Event::MouseDown { point, modifiers }, surface);
+    layers.on_event(Event::MouseUp { point, modifiers }, surface);
+}
+
+pub fn key(layers: &mut Layers, surface: &Recorder, key: &str, modifiers: Modifiers) {
+    let key = key.to_string();
+    layers.on_event(Event::KeyDown { key, modifiers }, surface);
+}
+
+pub fn key_up(layers: &mut Layers, surface: &Recorder, key: &str, modifiers: Modifiers) {
+    let key = key.to_string();
+    layers.on_event(Event::KeyUp { key, modifiers }, surface);
+}
+
+/// Layers 1 and 2, rects from (10, 10) to (50, 30) and from (100, 10) to (150, 30), with the
+/// select tool active.
+pub fn two_rects(surface: &Recorder) -> Layers {
+    let mut layers = Layers::new();
+    draw(
+        &mut layers,
+        surface,
+        ObjectKind::Rect,
+        (10.0, 10.0),
+        (50.0, 30.0),
+    );
+    draw(
+        &mut layers,
+        surface,
+        ObjectKind::Rect,
+        (100.0, 10.0),
+        (150.0, 30.0),
+    );
+    layers.set_tool(Tool::Select);
+    layers
+}
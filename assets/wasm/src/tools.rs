@@ -0,0 +1,58 @@
+use crate::objects::ObjectKind;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tool {
+    /// Click to select, drag objects to move them, drag empty space to marquee select.
+    Select,
+    /// Drag anywhere to create a new object of the given kind.
+    Create(ObjectKind),
+    /// Drags scroll the view instead of selecting, moving or creating objects.
+    Pan,
+}
+
+impl Tool {
+    pub fn from_name(name: &str) -> Option<Tool> {
+        match name {
+            "select" => Some(Tool::Select),
+            "rect" => Some(Tool::Create(ObjectKind::Rect)),
+            "ellipse" => Some(Tool::Create(ObjectKind::Ellipse)),
+            "line" => Some(Tool::Create(ObjectKind::Line)),
+            "text" => Some(Tool::Create(ObjectKind::Text)),
+            "pan" => Some(Tool::Pan),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Tool::Select => "select",
+            Tool::Create(ObjectKind::Rect) => "rect",
+            Tool::Create(ObjectKind::Ellipse) => "ellipse",
+            Tool::Create(ObjectKind::Line) => "line",
+            Tool::Create(ObjectKind::Text) => "text",
+            Tool::Pan => "pan",
+        }
+    }
+
+    pub fn cursor(&self) -> &str {
+        match self {
+            Tool::Select => "auto",
+            Tool::Create(ObjectKind::Text) => "text",
+            Tool::Create(_) => "crosshair",
+            Tool::Pan => "grab",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_tool_names() {
+        for name in ["select", "rect", "ellipse", "line", "text", "pan"] {
+            assert_eq!(Tool::from_name(name).unwrap().name(), name);
+        }
+        assert_eq!(Tool::from_name("polygon"), None);
+    }
+}
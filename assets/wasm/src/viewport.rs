@@ -0,0 +1,103 @@
+use crate::events::Point;
+use crate::events::Transform;
+use crate::objects::Object;
+use crate::objects::Rect;
+
+const MIN_ZOOM: f64 = 0.1;
+const MAX_ZOOM: f64 = 32.0;
+/// Space left around the document by `fit`, in canvas pixels.
+const FIT_PADDING: f64 = 20.0;
+
+/// The part of the document shown on the canvas. A document point `p` is drawn at
+/// `p * zoom + offset`, so `offset` is in canvas pixels.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Viewport {
+    offset: Point,
+    zoom: f64,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Viewport { offset: Point { x: 0.0, y: 0.0 }, zoom: 1.0 }
+    }
+}
+
+impl Viewport {
+    pub fn zoom(&self) -> f64 {
+        self.zoom
+    }
+
+    /// Maps the document onto the canvas.
+    pub fn transform(&self) -> Transform {
+        Transform::scale(self.zoom, self.zoom)
+            .then(&Transform::translate(self.offset.x, self.offset.y))
+    }
+
+    /// Maps a point on the canvas into the document.
+    pub fn to_world(&self, point: Point) -> Point {
+        point.subtract(self.offset).scale(1.0 / self.zoom)
+    }
+
+    /// Maps a point in the document onto the canvas.
+    pub fn to_screen(&self, point: Point) -> Point {
+        point.scale(self.zoom).add(self.offset)
+    }
+
+    /// Scrolls the view by `offset` canvas pixels.
+    pub fn pan(&mut self, offset: Point) {
+        self.offset = self.offset.add(offset);
+    }
+
+    /// Changes the zoom while keeping the document point under `anchor`, a point on the
+    /// canvas, in place.
+    pub fn zoom_at(&mut self, zoom: f64, anchor: Point) {
+        let world = self.to_world(anchor);
+        self.zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        self.offset = anchor.subtract(world.scale(self.zoom));
+    }
+
+    /// Zooms and pans so that `bounds` fills a `width` by `height` canvas, centered.
+    pub fn fit(&mut self, bounds: &Rect, width: f64, height: f64) {
+        let zoom_x = (width - 2.0 * FIT_PADDING) / (bounds.right() - bounds.left());
+        let zoom_y = (height - 2.0 * FIT_PADDING) / (bounds.bottom() - bounds.top());
+        self.zoom = zoom_x.min(zoom_y).clamp(MIN_ZOOM, MAX_ZOOM);
+        let center = Point { x: width / 2.0, y: height / 2.0 };
+        self.offset = center.subtract(bounds.center().scale(self.zoom));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zooms_around_anchor() {
+        let mut viewport = Viewport::default();
+        viewport.pan(Point { x: 10.0, y: 0.0 });
+        let anchor = Point { x: 110.0, y: 50.0 };
+
+        viewport.zoom_at(2.0, anchor);
+
+        assert_eq!(viewport.to_world(anchor), Point { x: 100.0, y: 50.0 });
+        assert_eq!(
+            viewport.to_screen(Point { x: 0.0, y: 0.0 }),
+            Point { x: -90.0, y: -50.0 }
+        );
+        viewport.zoom_at(1000.0, anchor);
+        assert_eq!(viewport.zoom(), MAX_ZOOM);
+    }
+
+    #[test]
+    fn fits_bounds_centered() {
+        let mut viewport = Viewport::default();
+        let bounds = Rect::new(Point { x: 100.0, y: 100.0 }, Point { x: 300.0, y: 150.0 });
+
+        viewport.fit(&bounds, 840.0, 440.0);
+
+        assert_eq!(viewport.zoom(), 4.0);
+        assert_eq!(
+            viewport.to_screen(bounds.center()),
+            Point { x: 420.0, y: 220.0 }
+        );
+    }
+}